@@ -0,0 +1,8 @@
+// build.rs
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // the messages and the server of `src/grpc.rs`
+    tonic_build::compile_protos("proto/persons.proto")?;
+    println!("cargo:rerun-if-changed=proto/persons.proto");
+    Ok(())
+}
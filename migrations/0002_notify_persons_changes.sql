@@ -0,0 +1,21 @@
+CREATE OR REPLACE FUNCTION notify_persons_change() RETURNS trigger AS $$
+DECLARE
+    person persons;
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        person := OLD;
+    ELSE
+        person := NEW;
+    END IF;
+    PERFORM pg_notify(
+        'persons_changes',
+        json_build_object('op', lower(TG_OP), 'person', row_to_json(person))::text
+    );
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS persons_notify ON persons;
+CREATE TRIGGER persons_notify
+    AFTER INSERT OR UPDATE OR DELETE ON persons
+    FOR EACH ROW EXECUTE PROCEDURE notify_persons_change();
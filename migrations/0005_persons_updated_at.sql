@@ -0,0 +1,14 @@
+ALTER TABLE persons ADD COLUMN IF NOT EXISTS updated_at TIMESTAMPTZ NOT NULL DEFAULT clock_timestamp();
+
+-- clock_timestamp, not now(): several writes of one transaction get distinct times
+CREATE OR REPLACE FUNCTION touch_updated_at() RETURNS trigger AS $$
+BEGIN
+    NEW.updated_at := clock_timestamp();
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS persons_touch ON persons;
+CREATE TRIGGER persons_touch
+    BEFORE UPDATE ON persons
+    FOR EACH ROW EXECUTE PROCEDURE touch_updated_at();
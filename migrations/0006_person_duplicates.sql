@@ -0,0 +1,14 @@
+-- needs a role allowed to create the extension, or pg_trgm installed beforehand
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+CREATE INDEX IF NOT EXISTS persons_name_trgm
+    ON persons USING gin (lower(first_name || ' ' || last_name) gin_trgm_ops);
+
+-- the ids of the merged rows, and the row each one now refers to
+CREATE TABLE IF NOT EXISTS merges (
+    resource TEXT NOT NULL,
+    merged_id INTEGER NOT NULL,
+    into_id INTEGER NOT NULL,
+    merged_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (resource, merged_id)
+);
@@ -0,0 +1,18 @@
+-- only the operation and the id: a notification payload must stay under 8000 bytes,
+-- the listeners read the row themselves
+CREATE OR REPLACE FUNCTION notify_persons_change() RETURNS trigger AS $$
+DECLARE
+    person persons;
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        person := OLD;
+    ELSE
+        person := NEW;
+    END IF;
+    PERFORM pg_notify(
+        'persons_changes',
+        json_build_object('op', lower(TG_OP), 'id', person.id)::text
+    );
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
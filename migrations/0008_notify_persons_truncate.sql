@@ -0,0 +1,12 @@
+-- a TRUNCATE fires no row trigger: the listeners are told to reload the whole list
+CREATE OR REPLACE FUNCTION notify_persons_truncate() RETURNS trigger AS $$
+BEGIN
+    PERFORM pg_notify('persons_changes', json_build_object('op', 'reset')::text);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS persons_notify_truncate ON persons;
+CREATE TRIGGER persons_notify_truncate
+    AFTER TRUNCATE ON persons
+    FOR EACH STATEMENT EXECUTE PROCEDURE notify_persons_truncate();
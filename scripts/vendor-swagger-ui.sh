@@ -0,0 +1,14 @@
+#!/bin/sh
+# Copies the Swagger UI of the /docs page into static/swagger-ui/,
+# from the swagger-ui-dist package of npm, to be committed with the other assets
+set -eu
+
+VERSION=3.32.5
+DEST="$(dirname "$0")/../static/swagger-ui"
+TMP="$(mktemp -d)"
+trap 'rm -rf "$TMP"' EXIT
+
+curl -fsSL "https://registry.npmjs.org/swagger-ui-dist/-/swagger-ui-dist-$VERSION.tgz" | tar -xz -C "$TMP"
+mkdir -p "$DEST"
+cp "$TMP/package/swagger-ui.css" "$TMP/package/swagger-ui-bundle.js" "$TMP/package/LICENSE" "$DEST/"
+echo "Swagger UI $VERSION copied to $DEST"
@@ -0,0 +1,142 @@
+// src/app.rs
+
+use sqlx::PgPool;
+use warp::filters::BoxedFilter;
+use warp::reply::Response;
+use warp::{Filter, Reply};
+
+use crate::cache;
+use crate::changes::ChangeFeed;
+use crate::config::Config;
+use crate::filters;
+use crate::handlers;
+use crate::jobs::{self, Job, Registry};
+use crate::repository::{PersonRepository, PgPersonRepository};
+use crate::webhooks;
+
+///
+/// The persons application, to be mounted in a bigger warp application
+///
+/// ```ignore
+/// let persons = PersonsApp::builder()
+///     .pool(pool)
+///     .base_path("/people")
+///     .build();
+/// let api = persons.or(other_routes).recover(handle_rejection).boxed();
+/// let (addr, server) = server::bind(api, addr, shutdown::wait_for_signal())?;
+/// server.await?;
+/// ```
+///
+/// `server::bind` (or `server::bind_tls`) gives every request its id and its span.
+/// `warp::serve` works too, the rate limits then key the clients by their remote address,
+/// but the requests get no id and the logs no request span.
+///
+pub struct PersonsApp;
+
+impl PersonsApp {
+    pub fn builder() -> PersonsAppBuilder {
+        PersonsAppBuilder {
+            pool: None,
+            base_path: String::new(),
+            config: None,
+            registry: Registry::new(),
+        }
+    }
+}
+
+pub struct PersonsAppBuilder {
+    pool: Option<PgPool>,
+    base_path: String,
+    config: Option<Config>,
+    registry: Registry,
+}
+
+impl PersonsAppBuilder {
+    ///
+    /// The pool of the database holding the persons, required
+    ///
+    pub fn pool(mut self, pool: PgPool) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
+    ///
+    /// Mounts the routes under `path`, `/people` gives `/people/persons`, ...
+    ///
+    pub fn base_path(mut self, path: &str) -> Self {
+        self.base_path = normalize(path);
+        self
+    }
+
+    ///
+    /// The configuration of the routes, read from the environment by default
+    ///
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    ///
+    /// Lets the job workers run the jobs of kind `J`
+    /// the jobs of the application are always registered
+    ///
+    pub fn job<J: Job>(mut self) -> Self {
+        self.registry = self.registry.register::<J>();
+        self
+    }
+
+    ///
+    /// Builds the filter of the routes
+    /// the requests it does not match are rejected as not found,
+    /// so it can be combined with `or` with other routes;
+    /// any other rejection is answered with an `ErrorMessage`
+    ///
+    /// It starts the listeners of the change feed and of the cache,
+    /// the webhook delivery worker and the job workers,
+    /// so it runs inside a tokio runtime.
+    ///
+    /// # Panics
+    /// if no pool was given
+    ///
+    pub fn build(self) -> BoxedFilter<(Response,)> {
+        let pool = self.pool.expect("PersonsApp needs a database pool");
+        let config = self.config.unwrap_or_else(Config::from_env);
+
+        let cache = cache::spawn(pool.clone(), config.cache.clone());
+        let repo = PgPersonRepository::new(pool.clone()).with_cache(cache);
+        let feed = ChangeFeed::spawn(pool.clone());
+        webhooks::worker::spawn(pool.clone(), config.webhooks.clone());
+        jobs::worker::spawn(pool.clone(), self.registry, config.jobs.clone());
+        mount(&self.base_path, pool, repo, feed, &config)
+    }
+}
+
+///
+/// The routes under `base_path`, with the recovery of `PersonsApp::build`
+/// but nothing started
+///
+pub(crate) fn mount<R: PersonRepository>(
+    base_path: &str,
+    pool: PgPool,
+    repo: R,
+    feed: ChangeFeed,
+    config: &Config,
+) -> BoxedFilter<(Response,)> {
+    filters::path_of(base_path)
+        .and(filters::routes(pool, repo, feed, config, base_path))
+        .recover(handlers::handle_rejection_except_not_found)
+        .map(Reply::into_response)
+        .boxed()
+}
+
+///
+/// `people/`, `/people/` and `/people` all give `/people`, `/` gives `""`
+///
+pub(crate) fn normalize(path: &str) -> String {
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    if segments.is_empty() {
+        String::new()
+    } else {
+        format!("/{}", segments.join("/"))
+    }
+}
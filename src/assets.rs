@@ -0,0 +1,169 @@
+// src/assets.rs
+
+use std::collections::HashMap;
+
+use hyper::Body;
+use once_cell::sync::Lazy;
+use rust_embed::RustEmbed;
+use sha2::{Digest, Sha256};
+use warp::http::header::{self, HeaderValue};
+use warp::http::StatusCode;
+use warp::reply::Response;
+
+/// Length of the content hash put in the asset file names.
+const HASH_LEN: usize = 16;
+
+///
+/// The static folder
+/// embedded in release builds, read from disk in debug builds
+///
+#[derive(RustEmbed)]
+#[folder = "static/"]
+struct Assets;
+
+///
+/// The content hashes of the embedded assets, computed once
+///
+static HASHES: Lazy<HashMap<String, String>> = Lazy::new(|| {
+    Assets::iter()
+        .filter_map(|name| {
+            let hash = compute_hash(&name)?;
+            Some((name.into_owned(), hash))
+        })
+        .collect()
+});
+
+fn compute_hash(name: &str) -> Option<String> {
+    let content = Assets::get(name)?;
+    let digest = format!("{:x}", Sha256::digest(&content));
+    Some(digest[..HASH_LEN].to_string())
+}
+
+///
+/// The content hash of an asset
+/// in debug builds it follows the file on disk
+///
+fn hash(name: &str) -> Option<String> {
+    if cfg!(debug_assertions) {
+        compute_hash(name)
+    } else {
+        HASHES.get(name).cloned()
+    }
+}
+
+///
+/// The fingerprinted url of an asset, `style.css` gives `/static/style.<hash>.css`
+/// unknown assets keep their plain url
+///
+pub fn url(name: &str) -> String {
+    match hash(name) {
+        Some(hash) => match name.rfind('.') {
+            Some(dot) => format!("/static/{}.{}{}", &name[..dot], hash, &name[dot..]),
+            None => format!("/static/{}.{}", name, hash),
+        },
+        None => format!("/static/{}", name),
+    }
+}
+
+///
+/// True when the static folder has the asset `name`
+///
+pub fn exists(name: &str) -> bool {
+    Assets::get(name).is_some()
+}
+
+///
+/// Tera function `asset_url(name="style.css")`
+///
+pub fn asset_url(args: &HashMap<String, tera::Value>) -> tera::Result<tera::Value> {
+    match args.get("name").and_then(|name| name.as_str()) {
+        Some(name) => Ok(tera::Value::String(url(name))),
+        None => Err(tera::Error::msg("asset_url needs a `name` argument")),
+    }
+}
+
+///
+/// Splits a requested file name into the asset name and the hash it carries
+/// `style.<hash>.css` gives (`style.css`, Some(hash)), `style.css` gives (`style.css`, None)
+///
+fn parse_name(file: &str) -> (String, Option<&str>) {
+    let mut parts: Vec<&str> = file.split('.').collect();
+    let position = match parts.len() {
+        0 | 1 => None,
+        2 => Some(1),
+        n => Some(n - 2),
+    };
+    if let Some(position) = position {
+        let candidate = parts[position];
+        if candidate.len() == HASH_LEN && candidate.chars().all(|c| c.is_ascii_hexdigit()) {
+            parts.remove(position);
+            return (parts.join("."), Some(candidate));
+        }
+    }
+    (file.to_string(), None)
+}
+
+///
+/// True when `file` stays inside the static folder
+/// in debug builds the assets are read from disk, `../Cargo.toml` would leave it
+///
+fn is_safe_name(file: &str) -> bool {
+    !file.is_empty()
+        && !file.starts_with('/')
+        && !file.starts_with('\\')
+        && !file.contains(':')
+        && !file.contains('\0')
+        && file
+            .split(|c| c == '/' || c == '\\')
+            .all(|segment| segment != "..")
+}
+
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match
+        .split(',')
+        .map(|tag| tag.trim().trim_start_matches("W/"))
+        .any(|tag| tag == "*" || tag == etag)
+}
+
+///
+/// Builds the reply for a requested file
+/// a url with the current hash is cached for a year, any other for no time,
+/// and the `ETag` gives a 304 to the clients having the same content
+///
+pub fn reply(file: &str, if_none_match: Option<String>) -> Option<Response> {
+    if !is_safe_name(file) {
+        return None;
+    }
+    let (name, requested_hash) = parse_name(file);
+    let content = Assets::get(&name)?;
+    let hash = hash(&name)?;
+    let etag = format!("\"{}\"", hash);
+
+    let cache_control = if requested_hash == Some(hash.as_str()) {
+        "public, max-age=31536000, immutable"
+    } else {
+        "no-cache"
+    };
+
+    let mut response = if if_none_match.map_or(false, |tags| etag_matches(&tags, &etag)) {
+        let mut response = Response::new(Body::empty());
+        *response.status_mut() = StatusCode::NOT_MODIFIED;
+        response
+    } else {
+        let mime = mime_guess::from_path(&name).first_or_octet_stream();
+        let mut response = Response::new(Body::from(content.into_owned()));
+        response.headers_mut().insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_str(mime.as_ref()).unwrap(),
+        );
+        response
+    };
+
+    let headers = response.headers_mut();
+    headers.insert(header::ETAG, HeaderValue::from_str(&etag).unwrap());
+    headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static(cache_control),
+    );
+    Some(response)
+}
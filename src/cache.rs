@@ -0,0 +1,340 @@
+// src/cache.rs
+
+use std::any::Any;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use sqlx::postgres::PgListener;
+use sqlx::PgPool;
+
+use crate::config::CacheConfig;
+use crate::db::PgTx;
+use crate::metrics;
+
+///
+/// The channel the write paths of `db` notify on, with `<table>:<id>`
+///
+pub const CHANNEL: &str = "cache_invalidation";
+
+/// The id of a notification about every row of a table
+const EVERY_ROW: &str = "*";
+
+/// Delay before listening again after the connection was lost
+const RETRY: Duration = Duration::from_secs(5);
+
+///
+/// A cached query: the list of a table, or one of its rows
+///
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Key {
+    table: String,
+    id: Option<i32>,
+}
+
+impl Key {
+    pub fn list(table: &str) -> Key {
+        Key {
+            table: table.to_string(),
+            id: None,
+        }
+    }
+
+    pub fn item(table: &str, id: i32) -> Key {
+        Key {
+            table: table.to_string(),
+            id: Some(id),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+}
+
+struct Entry {
+    value: Box<dyn Any + Send + Sync>,
+    expires_at: Instant,
+}
+
+struct Entries {
+    map: HashMap<Key, Entry>,
+    /// the keys of `map` in insertion order, the oldest is evicted first
+    order: VecDeque<Key>,
+    /// bumped by every invalidation, see `Cache::insert`
+    generation: u64,
+}
+
+impl Entries {
+    /// Removes `key` from the map and from the order
+    fn remove(&mut self, key: &Key) {
+        if self.map.remove(key).is_some() {
+            self.order.retain(|other| other != key);
+        }
+    }
+
+    fn remove_expired(&mut self) {
+        let now = Instant::now();
+        let Entries { map, order, .. } = self;
+        map.retain(|_, entry| entry.expires_at > now);
+        order.retain(|key| map.contains_key(key));
+    }
+}
+
+///
+/// A bounded cache of query results, each kept at most `ttl`
+///
+pub struct Cache {
+    capacity: usize,
+    ttl: Duration,
+    entries: Mutex<Entries>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl Cache {
+    pub fn new(capacity: usize, ttl: Duration) -> Cache {
+        Cache {
+            capacity,
+            ttl,
+            entries: Mutex::new(Entries {
+                map: HashMap::new(),
+                order: VecDeque::new(),
+                generation: 0,
+            }),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    ///
+    /// Runs `f` on the entries, keeping the `cache_entries` gauge up to date
+    ///
+    fn update<R>(&self, f: impl FnOnce(&mut Entries) -> R) -> R {
+        let mut entries = self.entries.lock().unwrap();
+        let before = entries.map.len();
+        let result = f(&mut entries);
+        metrics::record_cache_entries(entries.map.len() as i64 - before as i64);
+        result
+    }
+
+    pub fn get<V: Clone + Send + Sync + 'static>(&self, key: &Key) -> Option<V> {
+        let value = self.update(|entries| match entries.map.get(key) {
+            Some(entry) if entry.expires_at > Instant::now() => {
+                entry.value.downcast_ref::<V>().cloned()
+            }
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        });
+
+        let counter = if value.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        metrics::record_cache(&key.table, value.is_some());
+        value
+    }
+
+    ///
+    /// The generation to give to `insert`, taken before running the query
+    ///
+    pub fn generation(&self) -> u64 {
+        self.entries.lock().unwrap().generation
+    }
+
+    ///
+    /// Caches the result of a query run since `generation`
+    /// it is dropped when an invalidation happened meanwhile, the result may be stale
+    ///
+    pub fn insert<V: Send + Sync + 'static>(&self, generation: u64, key: Key, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        let capacity = self.capacity;
+        let ttl = self.ttl;
+        self.update(|entries| {
+            if entries.generation != generation {
+                return;
+            }
+            if entries.map.len() >= capacity && !entries.map.contains_key(&key) {
+                entries.remove_expired();
+            }
+            while entries.map.len() >= capacity {
+                match entries.order.pop_front() {
+                    Some(oldest) => {
+                        entries.map.remove(&oldest);
+                    }
+                    None => break,
+                }
+            }
+            let entry = Entry {
+                value: Box::new(value),
+                expires_at: Instant::now() + ttl,
+            };
+            if entries.map.insert(key.clone(), entry).is_none() {
+                entries.order.push_back(key);
+            }
+        });
+    }
+
+    ///
+    /// Forgets a row of `table` and the list of `table`
+    ///
+    pub fn invalidate(&self, table: &str, id: i32) {
+        self.update(|entries| {
+            entries.generation += 1;
+            entries.remove(&Key::item(table, id));
+            entries.remove(&Key::list(table));
+        });
+    }
+
+    ///
+    /// Forgets every row of `table` and the list of `table`
+    ///
+    pub fn invalidate_table(&self, table: &str) {
+        self.update(|entries| {
+            entries.generation += 1;
+            let Entries { map, order, .. } = entries;
+            map.retain(|key, _| key.table != table);
+            order.retain(|key| key.table != table);
+        });
+    }
+
+    pub fn clear(&self) {
+        self.update(|entries| {
+            entries.generation += 1;
+            entries.map.clear();
+            entries.order.clear();
+        });
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: self.entries.lock().unwrap().map.len(),
+        }
+    }
+
+    /// The keys in eviction order, the oldest first
+    pub(crate) fn order(&self) -> Vec<Key> {
+        self.entries.lock().unwrap().order.iter().cloned().collect()
+    }
+}
+
+impl Drop for Cache {
+    fn drop(&mut self) {
+        let entries = self.entries.lock().unwrap().map.len();
+        metrics::record_cache_entries(-(entries as i64));
+    }
+}
+
+///
+/// Creates the cache of an app, `None` when it is disabled,
+/// and starts the listener invalidating it on the writes of every instance;
+/// the listener stops once the cache is dropped
+///
+pub fn spawn(pool: PgPool, config: CacheConfig) -> Option<Arc<Cache>> {
+    if !config.enabled {
+        return None;
+    }
+    let cache = Arc::new(Cache::new(config.capacity, config.ttl));
+    tracing::info!(
+        "CACHE : {} entries kept at most {:?}",
+        config.capacity,
+        config.ttl
+    );
+    tokio::spawn(listen(pool, Arc::downgrade(&cache)));
+    Some(cache)
+}
+
+///
+/// Notifies every instance that a row of `table` changed
+/// sent inside the transaction `tx`, so it is delivered when the change is committed
+///
+pub async fn notify_in(tx: &mut PgTx, table: &str, id: i32) -> anyhow::Result<()> {
+    sqlx::query("SELECT pg_notify($1, $2);")
+        .bind(CHANNEL)
+        .bind(format!("{}:{}", table, id))
+        .execute(tx)
+        .await?;
+    Ok(())
+}
+
+///
+/// Notifies every instance that all the rows of `table` changed
+/// sent inside the transaction `tx`, like `notify_in`
+///
+pub async fn notify_table_in(tx: &mut PgTx, table: &str) -> anyhow::Result<()> {
+    sqlx::query("SELECT pg_notify($1, $2);")
+        .bind(CHANNEL)
+        .bind(format!("{}:{}", table, EVERY_ROW))
+        .execute(tx)
+        .await?;
+    Ok(())
+}
+
+///
+/// Listens to the notifications for good,
+/// connecting again whenever the connection is lost
+///
+async fn listen(pool: PgPool, cache: Weak<Cache>) {
+    loop {
+        match listen_once(&pool, &cache).await {
+            Ok(()) => {
+                tracing::info!("CACHE : dropped, listener stopped");
+                return;
+            }
+            Err(err) => tracing::warn!(
+                "CACHE : listener stopped : {}, retrying in {:?}",
+                err,
+                RETRY
+            ),
+        }
+        tokio::time::delay_for(RETRY).await;
+    }
+}
+
+///
+/// Listens until the connection is lost, an error,
+/// or until the cache is dropped
+///
+async fn listen_once(pool: &PgPool, cache: &Weak<Cache>) -> anyhow::Result<()> {
+    let mut listener = PgListener::from_pool(pool).await?;
+    listener.listen(CHANNEL).await?;
+    // the notifications sent while not listening are lost
+    match cache.upgrade() {
+        Some(cache) => cache.clear(),
+        None => return Ok(()),
+    }
+    tracing::info!("CACHE : listening on {}", CHANNEL);
+    loop {
+        let notification = listener.recv().await?;
+        let cache = match cache.upgrade() {
+            Some(cache) => cache,
+            None => return Ok(()),
+        };
+        let payload = notification.payload();
+        let parsed = payload
+            .rfind(':')
+            .map(|colon| (&payload[..colon], &payload[colon + 1..]));
+        match parsed {
+            Some((table, EVERY_ROW)) => cache.invalidate_table(table),
+            Some((table, id)) => match id.parse() {
+                Ok(id) => cache.invalidate(table, id),
+                Err(_) => tracing::warn!("CACHE : unreadable notification : {}", payload),
+            },
+            None => tracing::warn!("CACHE : unreadable notification : {}", payload),
+        }
+    }
+}
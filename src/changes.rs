@@ -0,0 +1,304 @@
+// src/changes.rs
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::{future, stream, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgListener;
+use sqlx::PgPool;
+use tokio::sync::broadcast;
+
+use crate::db;
+use crate::models::Person;
+
+///
+/// The channel the `persons` trigger notifies on
+///
+pub const CHANNEL: &str = "persons_changes";
+
+/// Number of changes kept for the clients resuming with `Last-Event-ID`
+pub(crate) const RECENT: usize = 256;
+
+/// Delay before listening again after the connection was lost
+const RETRY: Duration = Duration::from_secs(5);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Op {
+    Insert,
+    Update,
+    Delete,
+}
+
+///
+/// A change of the `persons` table, as notified by the triggers
+/// `Reset` when the table was truncated
+///
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum Notice {
+    Insert { id: i32 },
+    Update { id: i32 },
+    Delete { id: i32 },
+    Reset,
+}
+
+///
+/// A change of the `persons` table, with the row read after the notification
+/// `person` is none for a delete
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Change {
+    pub op: Op,
+    pub id: i32,
+    pub person: Option<Person>,
+}
+
+///
+/// The position of an event in the feed, `<epoch>-<seq>`
+/// the epoch changes whenever the listener (re)connects,
+/// since the notifications sent meanwhile are lost
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventId {
+    epoch: Arc<str>,
+    seq: u64,
+}
+
+impl fmt::Display for EventId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.epoch, self.seq)
+    }
+}
+
+impl FromStr for EventId {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let dash = s.rfind('-').ok_or(())?;
+        Ok(EventId {
+            epoch: Arc::from(&s[..dash]),
+            seq: s[dash + 1..].parse().map_err(|_| ())?,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Event {
+    Change {
+        id: EventId,
+        change: Change,
+    },
+    /// Changes were missed, the client has to reload the list
+    Reset {
+        id: EventId,
+    },
+}
+
+struct Recent {
+    epoch: Arc<str>,
+    seq: u64,
+    changes: VecDeque<(u64, Change)>,
+}
+
+impl Recent {
+    fn id(&self, seq: u64) -> EventId {
+        EventId {
+            epoch: self.epoch.clone(),
+            seq,
+        }
+    }
+}
+
+///
+/// Fans the notifications of the `persons` table out to the subscribers
+/// a single listener connection serves every open list page
+///
+#[derive(Clone)]
+pub struct ChangeFeed {
+    sender: broadcast::Sender<Event>,
+    recent: Arc<Mutex<Recent>>,
+}
+
+impl ChangeFeed {
+    ///
+    /// A feed nothing is published on until `listen` runs
+    ///
+    pub fn new() -> ChangeFeed {
+        let (sender, _) = broadcast::channel(RECENT);
+        ChangeFeed {
+            sender,
+            recent: Arc::new(Mutex::new(Recent {
+                epoch: new_epoch(),
+                seq: 0,
+                changes: VecDeque::with_capacity(RECENT),
+            })),
+        }
+    }
+
+    ///
+    /// Starts the listener task on a connection of the pool
+    ///
+    pub fn spawn(pool: PgPool) -> ChangeFeed {
+        let feed = ChangeFeed::new();
+        tokio::spawn(feed.clone().listen(pool));
+        feed
+    }
+
+    ///
+    /// Listens to the notifications for good,
+    /// connecting again whenever the connection is lost
+    ///
+    pub async fn listen(self, pool: PgPool) {
+        let mut connected_once = false;
+        loop {
+            if connected_once {
+                self.reset();
+            }
+            connected_once = true;
+            if let Err(err) = self.listen_once(&pool).await {
+                tracing::warn!(
+                    "CHANGES : listener stopped : {}, retrying in {:?}",
+                    err,
+                    RETRY
+                );
+            }
+            tokio::time::delay_for(RETRY).await;
+        }
+    }
+
+    async fn listen_once(&self, pool: &PgPool) -> anyhow::Result<()> {
+        let mut listener = PgListener::from_pool(pool).await?;
+        listener.listen(CHANNEL).await?;
+        tracing::info!("CHANGES : listening on {}", CHANNEL);
+        loop {
+            let notification = listener.recv().await?;
+            match serde_json::from_str::<Notice>(notification.payload()) {
+                Ok(Notice::Insert { id }) => self.read_and_publish(pool, Op::Insert, id).await,
+                Ok(Notice::Update { id }) => self.read_and_publish(pool, Op::Update, id).await,
+                Ok(Notice::Delete { id }) => self.read_and_publish(pool, Op::Delete, id).await,
+                Ok(Notice::Reset) => self.reset(),
+                Err(err) => tracing::warn!("CHANGES : unreadable notification : {}", err),
+            }
+        }
+    }
+
+    ///
+    /// Publishes the change with the row as it is now
+    /// a row already deleted is skipped, its delete follows;
+    /// a row that cannot be read resets the subscribers, which reload the list
+    ///
+    async fn read_and_publish(&self, pool: &PgPool, op: Op, id: i32) {
+        let person = match op {
+            Op::Delete => None,
+            Op::Insert | Op::Update => match db::find::<Person>(id, pool).await {
+                Ok(person) => Some(person),
+                Err(err) => match err.downcast_ref::<sqlx::Error>() {
+                    Some(sqlx::Error::RowNotFound) => return,
+                    _ => {
+                        tracing::warn!("CHANGES : could not read person {} : {}", id, err);
+                        self.reset();
+                        return;
+                    }
+                },
+            },
+        };
+        self.publish(Change { op, id, person });
+    }
+
+    ///
+    /// Numbers a change and sends it to the subscribers
+    /// under the lock, so a subscriber never sees it both replayed and live
+    ///
+    pub fn publish(&self, change: Change) {
+        let mut recent = self.recent.lock().unwrap();
+        recent.seq += 1;
+        let seq = recent.seq;
+        if recent.changes.len() == RECENT {
+            recent.changes.pop_front();
+        }
+        recent.changes.push_back((seq, change.clone()));
+        // no subscriber is not an error
+        let _ = self.sender.send(Event::Change {
+            id: recent.id(seq),
+            change,
+        });
+    }
+
+    ///
+    /// Starts a new epoch, the changes of the previous one can no longer be resumed
+    ///
+    pub(crate) fn reset(&self) {
+        let mut recent = self.recent.lock().unwrap();
+        recent.epoch = new_epoch();
+        recent.seq = 0;
+        recent.changes.clear();
+        let _ = self.sender.send(Event::Reset { id: recent.id(0) });
+    }
+
+    ///
+    /// The events following `last_event_id`: the recent changes it missed, then the live ones
+    /// a `Reset` comes first when the missed changes are no longer known
+    ///
+    pub fn subscribe(&self, last_event_id: Option<&str>) -> impl Stream<Item = Event> {
+        let recent = self.recent.lock().unwrap();
+        let receiver = self.sender.subscribe();
+
+        let backlog: Vec<Event> = match last_event_id.map(str::parse::<EventId>) {
+            None => Vec::new(),
+            Some(Ok(last)) if last.epoch == recent.epoch && last.seq <= recent.seq => {
+                let oldest = recent
+                    .changes
+                    .front()
+                    .map_or(recent.seq + 1, |(seq, _)| *seq);
+                if last.seq + 1 < oldest {
+                    vec![Event::Reset {
+                        id: recent.id(recent.seq),
+                    }]
+                } else {
+                    recent
+                        .changes
+                        .iter()
+                        .filter(|(seq, _)| *seq > last.seq)
+                        .map(|(seq, change)| Event::Change {
+                            id: recent.id(*seq),
+                            change: change.clone(),
+                        })
+                        .collect()
+                }
+            }
+            Some(_) => vec![Event::Reset {
+                id: recent.id(recent.seq),
+            }],
+        };
+        let current = recent.id(recent.seq);
+        drop(recent);
+
+        let live = receiver.filter_map(move |received| {
+            future::ready(match received {
+                Ok(event) => Some(event),
+                // this subscriber is too slow, it missed changes
+                Err(broadcast::RecvError::Lagged(_)) => Some(Event::Reset {
+                    id: current.clone(),
+                }),
+                Err(broadcast::RecvError::Closed) => None,
+            })
+        });
+        stream::iter(backlog).chain(live)
+    }
+}
+
+impl Default for ChangeFeed {
+    fn default() -> Self {
+        ChangeFeed::new()
+    }
+}
+
+fn new_epoch() -> Arc<str> {
+    let uuid = uuid::Uuid::new_v4().to_simple().to_string();
+    Arc::from(&uuid[..8])
+}
@@ -0,0 +1,437 @@
+// src/cli.rs
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::Serialize;
+use serde_json::json;
+use sqlx::PgPool;
+use structopt::StructOpt;
+
+use crate::config::Config;
+use crate::db;
+use crate::handlers;
+use crate::jobs::{ExportPersons, Job, JobContext};
+use crate::migrations;
+use crate::models::{HealthStatus, InsertablePerson, Person};
+use crate::resource::Resource;
+use crate::seed::{self, FixtureSet, FIXTURE_SETS};
+
+///
+/// The command line of the binary
+/// without a command it serves the application
+///
+#[derive(StructOpt, Debug)]
+#[structopt(about = "The persons directory: its servers and its administration")]
+pub struct Cli {
+    /// How the results are printed: table or json
+    #[structopt(long, short, global = true, default_value = "table")]
+    pub output: Output,
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(StructOpt, Debug)]
+pub enum Command {
+    /// Runs the HTTP and gRPC servers, the default
+    Serve,
+    /// Applies the pending migrations
+    Migrate,
+    /// Lists, shows and edits the persons
+    Persons(PersonsCommand),
+    /// Adds the persons of a CSV file with `first_name` and `last_name` columns, all or none
+    Import {
+        #[structopt(parse(from_os_str))]
+        path: PathBuf,
+    },
+    /// Writes the persons to a CSV file, in `JOB_EXPORT_DIR` by default
+    Export {
+        #[structopt(parse(from_os_str))]
+        path: Option<PathBuf>,
+    },
+    /// Checks the database and the migrations, fails when one of them is down
+    CheckDb,
+    /// Fills an empty persons table with generated persons, or with a fixture set
+    Seed {
+        /// How many persons to generate
+        #[structopt(long, default_value = "50")]
+        count: usize,
+        /// The same seed always generates the same persons
+        #[structopt(long, default_value = "42")]
+        seed: u64,
+        /// Loads this fixture set instead: basic, duplicates or demo
+        #[structopt(long)]
+        fixtures: Option<String>,
+        /// Empties the table first and restarts the ids at 1
+        #[structopt(long)]
+        reset: bool,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+pub enum PersonsCommand {
+    List,
+    Get {
+        id: i32,
+    },
+    Add {
+        first_name: String,
+        last_name: String,
+    },
+    Update {
+        id: i32,
+        first_name: String,
+        last_name: String,
+    },
+    Delete {
+        id: i32,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Output {
+    Table,
+    Json,
+}
+
+impl FromStr for Output {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(Output::Table),
+            "json" => Ok(Output::Json),
+            _ => Err(format!("unknown output {}, expected table or json", s)),
+        }
+    }
+}
+
+///
+/// Runs an administration command against the database of `config`
+/// `serve` is run by the binary itself
+///
+pub async fn run(command: Command, output: Output, config: &Config) -> anyhow::Result<()> {
+    let pool = db::create_pg_pool(&config.database_url).await?;
+    let result = match command {
+        Command::Serve => Err(anyhow::anyhow!("serve is not an administration command")),
+        Command::Migrate => migrate(&pool, output).await,
+        Command::Persons(command) => persons(command, &pool, output).await,
+        Command::Import { path } => import(&path, &pool, output).await,
+        Command::Export { path } => export(path, &pool, config, output).await,
+        Command::CheckDb => check_db(&pool, config, output).await,
+        Command::Seed {
+            count,
+            seed,
+            fixtures,
+            reset,
+        } => {
+            let persons = match fixtures {
+                Some(name) => fixture_set(&name)?.persons(),
+                None => seed::generate(count, seed),
+            };
+            let persons = seed::load(&pool, persons, reset).await?;
+            print_persons(output, &persons);
+            Ok(())
+        }
+    };
+    pool.close().await;
+    result
+}
+
+async fn migrate(pool: &PgPool, output: Output) -> anyhow::Result<()> {
+    let applied = migrations::run(pool).await?;
+    let rows = applied.iter().map(|version| vec![version.to_string()]);
+    print(output, &json!({ "applied": applied }), &["applied"], rows);
+    Ok(())
+}
+
+async fn persons(command: PersonsCommand, pool: &PgPool, output: Output) -> anyhow::Result<()> {
+    match command {
+        PersonsCommand::List => {
+            let persons = db::list::<Person>(pool).await?;
+            print_persons(output, &persons);
+        }
+        PersonsCommand::Get { id } => {
+            let person = db::find::<Person>(id, pool)
+                .await
+                .map_err(|err| not_found(err, id))?;
+            print_persons(output, &[person]);
+        }
+        PersonsCommand::Add {
+            first_name,
+            last_name,
+        } => {
+            let person = checked(first_name, last_name)?;
+            let person = db::add::<Person>(pool, person).await?;
+            print_persons(output, &[person]);
+        }
+        PersonsCommand::Update {
+            id,
+            first_name,
+            last_name,
+        } => {
+            let person = checked(first_name, last_name)?;
+            let person = db::update::<Person>(id, person, pool)
+                .await
+                .map_err(|err| not_found(err, id))?;
+            print_persons(output, &[person]);
+        }
+        PersonsCommand::Delete { id } => {
+            if db::delete::<Person>(id, pool).await? == 0 {
+                anyhow::bail!("no {} with id {}", Person::NAME, id);
+            }
+            let rows = std::iter::once(vec![id.to_string()]);
+            print(output, &json!({ "deleted": id }), &["deleted"], rows);
+        }
+    }
+    Ok(())
+}
+
+///
+/// Adds every person of the file in one transaction,
+/// nothing is added when one of them is refused
+///
+async fn import(path: &Path, pool: &PgPool, output: Output) -> anyhow::Result<()> {
+    let text = tokio::fs::read_to_string(path).await?;
+    let persons = read_persons(&text)?;
+
+    let mut tx = db::begin(pool).await?;
+    for person in &persons {
+        db::add_in::<Person>(&mut tx, person.clone()).await?;
+    }
+    tx.commit().await?;
+
+    let rows = std::iter::once(vec![persons.len().to_string()]);
+    print(
+        output,
+        &json!({ "imported": persons.len() }),
+        &["imported"],
+        rows,
+    );
+    Ok(())
+}
+
+///
+/// Runs the export job right away, instead of queuing it
+///
+async fn export(
+    path: Option<PathBuf>,
+    pool: &PgPool,
+    config: &Config,
+    output: Output,
+) -> anyhow::Result<()> {
+    let job = match path {
+        Some(path) => ExportPersons { path },
+        None => ExportPersons::timestamped(&config.jobs.export_dir),
+    };
+    let path = job.path.clone();
+    job.run(JobContext { pool: pool.clone() }).await?;
+
+    let rows = std::iter::once(vec![path.display().to_string()]);
+    print(output, &json!({ "path": path }), &["path"], rows);
+    Ok(())
+}
+
+async fn check_db(pool: &PgPool, config: &Config, output: Output) -> anyhow::Result<()> {
+    let report = handlers::readiness(pool, config.readiness_timeout).await;
+    let rows = report.components.iter().map(|(name, health)| {
+        vec![
+            name.to_string(),
+            status(health.status).to_string(),
+            format!("{:.1}", health.latency_ms),
+            health.error.clone().unwrap_or_default(),
+        ]
+    });
+    print(
+        output,
+        &report,
+        &["component", "status", "latency_ms", "error"],
+        rows,
+    );
+    match report.status {
+        HealthStatus::Up => Ok(()),
+        HealthStatus::Down => Err(anyhow::anyhow!("the database is not ready")),
+    }
+}
+
+fn fixture_set(name: &str) -> anyhow::Result<&'static FixtureSet> {
+    seed::fixtures(name).ok_or_else(|| {
+        let names: Vec<&str> = FIXTURE_SETS.iter().map(|set| set.name).collect();
+        anyhow::anyhow!(
+            "no fixture set {}, expected one of {}",
+            name,
+            names.join(", ")
+        )
+    })
+}
+
+fn status(status: HealthStatus) -> &'static str {
+    match status {
+        HealthStatus::Up => "up",
+        HealthStatus::Down => "down",
+    }
+}
+
+fn checked(first_name: String, last_name: String) -> anyhow::Result<InsertablePerson> {
+    let person = InsertablePerson {
+        first_name,
+        last_name,
+    };
+    Person::validate(&person).map_err(|invalid| anyhow::anyhow!("{}", invalid))?;
+    Ok(person)
+}
+
+fn not_found(err: anyhow::Error, id: i32) -> anyhow::Error {
+    match err.downcast_ref::<sqlx::Error>() {
+        Some(sqlx::Error::RowNotFound) => anyhow::anyhow!("no {} with id {}", Person::NAME, id),
+        _ => err,
+    }
+}
+
+fn print_persons(output: Output, persons: &[Person]) {
+    let rows = persons.iter().map(|person| {
+        vec![
+            person.id.to_string(),
+            person.first_name.clone(),
+            person.last_name.clone(),
+        ]
+    });
+    print(output, &persons, &["id", "first_name", "last_name"], rows);
+}
+
+///
+/// Prints `value` as JSON, or `rows` as a table
+///
+fn print<T: Serialize>(
+    output: Output,
+    value: &T,
+    headers: &[&str],
+    rows: impl Iterator<Item = Vec<String>>,
+) {
+    match output {
+        Output::Json => match serde_json::to_string_pretty(value) {
+            Ok(json) => println!("{}", json),
+            Err(err) => eprintln!("could not encode the result : {}", err),
+        },
+        Output::Table => print!("{}", table(headers, &rows.collect::<Vec<_>>())),
+    }
+}
+
+///
+/// Lays `rows` out in columns under `headers`
+/// widths are counted in characters, `Léon` is 4 wide
+///
+pub(crate) fn table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = headers
+        .iter()
+        .map(|header| header.chars().count())
+        .collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let separators: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+    let mut table = line(headers, &widths);
+    table.push_str(&line(&separators, &widths));
+    for row in rows {
+        table.push_str(&line(row, &widths));
+    }
+    table
+}
+
+fn line<S: AsRef<str>>(cells: &[S], widths: &[usize]) -> String {
+    let padded: Vec<String> = cells
+        .iter()
+        .zip(widths)
+        .map(|(cell, width)| {
+            let cell = cell.as_ref();
+            format!("{}{}", cell, " ".repeat(width - cell.chars().count()))
+        })
+        .collect();
+    format!("{}\n", padded.join("  ").trim_end())
+}
+
+///
+/// The persons of a CSV file, checked as the forms are
+/// the header names the columns, `first_name` and `last_name` are required,
+/// the others (an exported `id`) are ignored
+///
+pub(crate) fn read_persons(text: &str) -> anyhow::Result<Vec<InsertablePerson>> {
+    let mut records = parse_csv(text)?.into_iter();
+    let header = records
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("the file is empty"))?;
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|column| column.trim() == name)
+            .ok_or_else(|| anyhow::anyhow!("no {} column", name))
+    };
+    let first_name = column("first_name")?;
+    let last_name = column("last_name")?;
+
+    let mut persons = Vec::new();
+    for (index, record) in records.enumerate() {
+        // the header is line 1
+        let line = index + 2;
+        if record.iter().all(|field| field.is_empty()) {
+            continue;
+        }
+        let field = |column: usize| record.get(column).cloned().unwrap_or_default();
+        let person = InsertablePerson {
+            first_name: field(first_name),
+            last_name: field(last_name),
+        };
+        Person::validate(&person)
+            .map_err(|invalid| anyhow::anyhow!("line {} : {}", line, invalid))?;
+        persons.push(person);
+    }
+    Ok(persons)
+}
+
+///
+/// Splits CSV text into records of fields, as the export writes them:
+/// a field holding a separator, a quote or a line break is quoted,
+/// its quotes doubled
+///
+pub(crate) fn parse_csv(text: &str) -> anyhow::Result<Vec<Vec<String>>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if quoted {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => quoted = false,
+                _ => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' => quoted = true,
+            ',' => record.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            _ => field.push(c),
+        }
+    }
+    if quoted {
+        anyhow::bail!("unterminated quoted field");
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    Ok(records)
+}
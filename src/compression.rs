@@ -0,0 +1,202 @@
+// src/compression.rs
+
+use std::io::Write;
+use std::sync::Arc;
+
+use flate2::write::GzEncoder;
+use hyper::Body;
+use warp::filters::BoxedFilter;
+use warp::http::header::{self, HeaderValue};
+use warp::http::StatusCode;
+use warp::reject;
+use warp::reply::Response;
+use warp::{Filter, Rejection, Reply};
+
+use crate::config::CompressionConfig;
+use crate::handlers::ServerError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+impl Encoding {
+    fn name(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    fn encode(self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            Encoding::Brotli => {
+                // quality 5 : most of the gain for a fraction of the time of 11
+                let mut writer = brotli::CompressorWriter::new(Vec::new(), 4096, 5, 22);
+                writer.write_all(data)?;
+                Ok(writer.into_inner())
+            }
+            Encoding::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+        }
+    }
+}
+
+///
+/// Compresses the replies of a filter with the encoding the client prefers
+/// among brotli and gzip, according to its `Accept-Encoding` header
+///
+pub fn compress<R: Reply + 'static>(
+    config: &CompressionConfig,
+    filter: BoxedFilter<(R,)>,
+) -> BoxedFilter<(Response,)> {
+    let config = Arc::new(config.clone());
+    warp::header::optional::<String>("accept-encoding")
+        .and(filter)
+        .and_then(move |accept: Option<String>, reply: R| {
+            compress_reply(config.clone(), accept, reply.into_response())
+        })
+        .boxed()
+}
+
+async fn compress_reply(
+    config: Arc<CompressionConfig>,
+    accept: Option<String>,
+    response: Response,
+) -> Result<Response, Rejection> {
+    if !config.enabled || !is_compressible(&config, &response) {
+        return Ok(response);
+    }
+    let (mut parts, body) = response.into_parts();
+    parts
+        .headers
+        .append(header::VARY, HeaderValue::from_static("accept-encoding"));
+
+    let encoding = match accept.as_deref().and_then(negotiate) {
+        Some(encoding) => encoding,
+        None => return Ok(Response::from_parts(parts, body)),
+    };
+
+    let bytes = hyper::body::to_bytes(body).await.map_err(|err| {
+        tracing::error!("COMPRESSION : error reading the body : {}", err);
+        reject::custom(ServerError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "COMPRESSION_ERROR",
+        ))
+    })?;
+    if bytes.len() < config.min_size {
+        return Ok(Response::from_parts(parts, Body::from(bytes)));
+    }
+
+    match encoding.encode(&bytes) {
+        Ok(compressed) => {
+            tracing::debug!(
+                "COMPRESSION : {} bytes -> {} bytes {}",
+                bytes.len(),
+                compressed.len(),
+                encoding.name()
+            );
+            parts.headers.remove(header::CONTENT_LENGTH);
+            parts.headers.insert(
+                header::CONTENT_ENCODING,
+                HeaderValue::from_static(encoding.name()),
+            );
+            encode_etag(&mut parts.headers, encoding);
+            Ok(Response::from_parts(parts, Body::from(compressed)))
+        }
+        Err(err) => {
+            tracing::warn!(
+                "COMPRESSION : {} failed, sent as is : {}",
+                encoding.name(),
+                err
+            );
+            Ok(Response::from_parts(parts, Body::from(bytes)))
+        }
+    }
+}
+
+///
+/// A strong ETag names the bytes sent, which the encoding changes:
+/// `"abc"` becomes `"abc-gzip"`, and `conditional` strips the suffix back
+///
+fn encode_etag(headers: &mut header::HeaderMap, encoding: Encoding) {
+    let etag = match headers.get(header::ETAG).and_then(|etag| etag.to_str().ok()) {
+        Some(etag) if etag.starts_with('"') && etag.ends_with('"') && etag.len() > 1 => {
+            format!("\"{}-{}\"", &etag[1..etag.len() - 1], encoding.name())
+        }
+        _ => return,
+    };
+    if let Ok(etag) = HeaderValue::from_str(&etag) {
+        headers.insert(header::ETAG, etag);
+    }
+}
+
+///
+/// Leaves alone the replies without body, already encoded,
+/// or of a content type compressed by nature
+///
+fn is_compressible(config: &CompressionConfig, response: &Response) -> bool {
+    if response.status() == StatusCode::NO_CONTENT
+        || response.status() == StatusCode::NOT_MODIFIED
+        || response.headers().contains_key(header::CONTENT_ENCODING)
+    {
+        return false;
+    }
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("");
+    let too_small = response
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok()?.parse::<usize>().ok())
+        .map_or(false, |len| len < config.min_size);
+
+    !too_small
+        && !config
+            .excluded_types
+            .iter()
+            .any(|excluded| content_type.starts_with(excluded.as_str()))
+}
+
+///
+/// Picks the encoding from an `Accept-Encoding` header
+/// brotli wins a tie, `*` stands for the encodings not listed,
+/// and `q=0` refuses an encoding
+///
+fn negotiate(accept: &str) -> Option<Encoding> {
+    let mut brotli = None;
+    let mut gzip = None;
+    let mut any = None;
+
+    for item in accept.split(',') {
+        let mut params = item.split(';');
+        let coding = params.next().unwrap_or("").trim().to_ascii_lowercase();
+        let quality = params
+            .filter_map(|param| param.trim().strip_prefix("q="))
+            .next()
+            .and_then(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        match coding.as_str() {
+            "br" => brotli = Some(quality),
+            "gzip" => gzip = Some(quality),
+            "*" => any = Some(quality),
+            _ => {}
+        }
+    }
+
+    let brotli = brotli.or(any).unwrap_or(0.0);
+    let gzip = gzip.or(any).unwrap_or(0.0);
+    if brotli > 0.0 && brotli >= gzip {
+        Some(Encoding::Brotli)
+    } else if gzip > 0.0 {
+        Some(Encoding::Gzip)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,137 @@
+// src/conditional.rs
+
+use std::convert::Infallible;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use sha2::{Digest, Sha256};
+use warp::http::header::{HeaderValue, ETAG, LAST_MODIFIED};
+use warp::http::StatusCode;
+use warp::reply::Response;
+use warp::{Filter, Reply};
+
+///
+/// The validators of a representation: its ETag and when it last changed
+///
+#[derive(Debug, Clone)]
+pub struct Validators {
+    etag: String,
+    last_modified: Option<SystemTime>,
+}
+
+impl Validators {
+    ///
+    /// A strong ETag, the digest of the body itself
+    ///
+    pub fn strong(body: &[u8], updated_at: Option<i64>) -> Validators {
+        let digest = format!("{:x}", Sha256::digest(body));
+        Validators {
+            etag: format!("\"{}\"", &digest[..32]),
+            last_modified: updated_at.map(to_system_time),
+        }
+    }
+
+    ///
+    /// A weak ETag, from the number of rows and their latest update
+    ///
+    pub fn weak(count: i64, updated_at: Option<i64>) -> Validators {
+        Validators {
+            etag: format!("W/\"{}-{}\"", count, updated_at.unwrap_or(0)),
+            last_modified: updated_at.map(to_system_time),
+        }
+    }
+
+    ///
+    /// Adds the `ETag` and `Last-Modified` headers to a reply
+    ///
+    pub fn apply(&self, mut response: Response) -> Response {
+        let headers = response.headers_mut();
+        if let Ok(etag) = HeaderValue::from_str(&self.etag) {
+            headers.insert(ETAG, etag);
+        }
+        if let Some(last_modified) = self.last_modified {
+            if let Ok(date) = HeaderValue::from_str(&httpdate::fmt_http_date(last_modified)) {
+                headers.insert(LAST_MODIFIED, date);
+            }
+        }
+        response
+    }
+
+    ///
+    /// The empty `304 Not Modified` answering a fresh request
+    ///
+    pub fn not_modified(&self) -> Response {
+        self.apply(StatusCode::NOT_MODIFIED.into_response())
+    }
+}
+
+///
+/// The `If-None-Match` and `If-Modified-Since` headers of a request
+///
+#[derive(Debug, Clone, Default)]
+pub struct Preconditions {
+    if_none_match: Option<String>,
+    if_modified_since: Option<String>,
+}
+
+impl Preconditions {
+    ///
+    /// True when the client already has the representation described by `validators`
+    /// `If-None-Match` wins over `If-Modified-Since`, compared weakly as a GET allows
+    ///
+    pub fn is_fresh(&self, validators: &Validators) -> bool {
+        if let Some(if_none_match) = &self.if_none_match {
+            let etag = opaque(&validators.etag);
+            return if_none_match
+                .split(',')
+                .map(str::trim)
+                .any(|candidate| candidate == "*" || opaque(candidate) == etag);
+        }
+        match (&self.if_modified_since, validators.last_modified) {
+            (Some(since), Some(last_modified)) => match httpdate::parse_http_date(since) {
+                // the dates only have a precision of one second
+                Ok(since) => truncate(last_modified) <= since,
+                Err(_) => false,
+            },
+            _ => false,
+        }
+    }
+}
+
+///
+/// Extracts the preconditions of the request
+///
+pub fn preconditions() -> impl Filter<Extract = (Preconditions,), Error = Infallible> + Clone {
+    warp::header::optional::<String>("if-none-match")
+        .and(warp::header::optional::<String>("if-modified-since"))
+        .map(|if_none_match, if_modified_since| Preconditions {
+            if_none_match,
+            if_modified_since,
+        })
+        .or(warp::any().map(Preconditions::default))
+        .unify()
+}
+
+///
+/// The ETag without its weakness indicator,
+/// nor the content coding `compression` appends to a strong one
+///
+fn opaque(etag: &str) -> String {
+    let etag = etag.trim_start_matches("W/");
+    for suffix in &["-gzip\"", "-br\""] {
+        if etag.ends_with(suffix) {
+            return format!("{}\"", &etag[..etag.len() - suffix.len()]);
+        }
+    }
+    etag.to_string()
+}
+
+fn to_system_time(millis: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(millis.max(0) as u64)
+}
+
+fn truncate(time: SystemTime) -> SystemTime {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    UNIX_EPOCH + Duration::from_secs(secs)
+}
@@ -1,9 +1,19 @@
 // src/db.rs
 
+use std::collections::HashMap;
+
+use anyhow::bail;
+use futures::StreamExt;
+use sqlx::pool::PoolConnection;
 use sqlx::postgres::PgRow;
-use sqlx::{PgConnection, PgPool, Row};
+use sqlx::{PgConnection, PgPool, Row, Transaction};
+use tokio::sync::mpsc;
+use tracing_futures::Instrument;
 
-use crate::models::{InsertablePerson, Person};
+use crate::cache;
+use crate::metrics::{self, PoolWaiter};
+use crate::resource::{self, Duplicate, ListVersion, Resource, Statements, Value};
+use crate::webhooks;
 //use crate::errors;
 
 /// Open a connection to a database
@@ -12,6 +22,47 @@ pub async fn create_pg_pool(db_url: &str) -> sqlx::Result<PgPool> {
     Ok(pool)
 }
 
+/// A transaction on a connection of the pool
+pub type PgTx = Transaction<PoolConnection<PgConnection>>;
+
+///
+/// Begins a transaction
+/// the time spent waiting for a connection shows in the pool metrics
+///
+pub async fn begin(pool: &PgPool) -> sqlx::Result<PgTx> {
+    let _waiter = PoolWaiter::enter();
+    pool.begin().await
+}
+
+///
+/// Runs a query in a `db` span carrying the statement name
+/// and records its latency in the metrics
+///
+async fn run<F, T>(statement: &str, fut: F) -> anyhow::Result<T>
+where
+    F: std::future::Future<Output = anyhow::Result<T>>,
+{
+    let span = tracing::info_span!("db", statement, rows = tracing::field::Empty);
+    metrics::time_query(statement, fut).instrument(span).await
+}
+
+///
+/// Records the number of rows returned or affected in the current `db` span
+///
+fn record_rows(rows: u64) {
+    tracing::Span::current().record("rows", &rows);
+    tracing::debug!("DB : {} row(s)", rows);
+}
+
+///
+/// Checks that a connection can be acquired from the pool and used
+///
+pub async fn ping(pool: &PgPool) -> anyhow::Result<()> {
+    let mut conn = pool.acquire().await?;
+    sqlx::query("SELECT 1;").execute(&mut conn).await?;
+    Ok(())
+}
+
 /*
 pub async fn get_db_con(db_pool: &PgPool) -> anyhow::Result<PgConnection> {
     db_pool.get().await.map_err(errors::CustError::DBPoolError)
@@ -30,115 +81,463 @@ fn row_to_person(row: &PgRow) -> Person {
 }
 */
 
-pub async fn list_persons(pool: &PgPool) -> anyhow::Result<Vec<Person>> {
-    let mut tx = pool.begin().await?;
+///
+/// Binds the values of a resource to the `$n` parameters, in order
+///
+macro_rules! bind_values {
+    ($query:expr, $values:expr) => {{
+        let mut query = $query;
+        for value in $values {
+            query = match value {
+                Value::Text(value) => query.bind(value),
+                Value::Int(value) => query.bind(value),
+                Value::Bool(value) => query.bind(value),
+            };
+        }
+        query
+    }};
+}
 
-    let mut persons: Vec<Person> = Vec::new();
-    let recs: Vec<Person> = sqlx::query(
-        "SELECT id, first_name, last_name
-                                        FROM persons
-                                        ORDER BY id;",
-    )
-    .map(|row: PgRow| Person {
-        id: row.get(0),
-        first_name: row.get(1),
-        last_name: row.get(2),
+///
+/// Lists the rows of a resource, ordered by id
+///
+pub async fn list<T: Resource>(pool: &PgPool) -> anyhow::Result<Vec<T>> {
+    run(&format!("list_{}", T::PLURAL), async {
+        let mut tx = begin(pool).await?;
+        let items = list_in::<T>(&mut tx).await?;
+        tx.commit().await?;
+        Ok(items)
     })
-    .fetch_all(&mut tx)
-    .await?;
-
-    tx.commit().await?;
+    .await
+}
 
-    for rec in recs {
-        persons.push(Person {
-            id: rec.id,
-            first_name: rec.first_name,
-            last_name: rec.last_name,
-        });
+///
+/// Sends the rows of a resource, ordered by id, to `tx` as the database returns them,
+/// then the error if the query failed; stops early once the receiver is dropped.
+/// The connection is held until the last row is sent, a slow receiver slows the query down
+/// rather than the rows piling up in memory
+///
+pub async fn stream_list<T: Resource>(pool: &PgPool, mut tx: mpsc::Sender<anyhow::Result<T>>) {
+    let statement = Statements::of::<T>().list;
+    let streamed = run(&format!("stream_{}", T::PLURAL), async {
+        let mut rows = Box::pin(
+            sqlx::query(&statement)
+                .map(|row: PgRow| T::from_row(&row))
+                .fetch(pool),
+        );
+        let mut sent = 0;
+        while let Some(item) = rows.next().await {
+            // the receiver went away
+            if tx.send(Ok(item?)).await.is_err() {
+                break;
+            }
+            sent += 1;
+        }
+        record_rows(sent);
+        Ok(())
+    })
+    .await;
+    if let Err(err) = streamed {
+        let _ = tx.send(Err(err)).await;
     }
+}
 
-    Ok(persons)
+/// `list` inside the transaction `tx`
+pub async fn list_in<T: Resource>(tx: &mut PgTx) -> anyhow::Result<Vec<T>> {
+    let statements = Statements::of::<T>();
+    let items: Vec<T> = sqlx::query(&statements.list)
+        .map(|row: PgRow| T::from_row(&row))
+        .fetch_all(tx)
+        .await?;
+    record_rows(items.len() as u64);
+    Ok(items)
 }
 
-pub async fn find_person_by_id(id: i32, pool: &PgPool) -> anyhow::Result<Person> {
-    let mut tx = pool.begin().await?;
-    let rec = sqlx::query("SELECT * FROM persons WHERE id = $1;")
+///
+/// One page of the rows of a resource whose `contains` columns contain their text,
+/// whatever the case, ordered by id; with the number of these rows on every page
+///
+pub async fn search<T: Resource>(
+    contains: &[(&str, &str)],
+    limit: i64,
+    offset: i64,
+    pool: &PgPool,
+) -> anyhow::Result<(Vec<T>, i64)> {
+    let columns: Vec<&str> = contains.iter().map(|(column, _)| *column).collect();
+    let patterns: Vec<String> = contains
+        .iter()
+        .map(|(_, part)| resource::contains_pattern(part))
+        .collect();
+    let (count_statement, page_statement) = Statements::search::<T>(&columns);
+    run(&format!("search_{}", T::PLURAL), async {
+        let mut tx = begin(pool).await?;
+        let mut count = sqlx::query(&count_statement);
+        for pattern in &patterns {
+            count = count.bind(pattern.clone());
+        }
+        let total: i64 = count
+            .map(|row: PgRow| row.get(0))
+            .fetch_one(&mut tx)
+            .await?;
+        let mut page = sqlx::query(&page_statement);
+        for pattern in &patterns {
+            page = page.bind(pattern.clone());
+        }
+        let items: Vec<T> = page
+            .bind(limit)
+            .bind(offset)
+            .map(|row: PgRow| T::from_row(&row))
+            .fetch_all(&mut tx)
+            .await?;
+        tx.commit().await?;
+        record_rows(items.len() as u64);
+        Ok((items, total))
+    })
+    .await
+}
+
+///
+/// Finds a row of a resource by id
+///
+pub async fn find<T: Resource>(id: i32, pool: &PgPool) -> anyhow::Result<T> {
+    run(&format!("find_{}_by_id", T::NAME), async {
+        let mut tx = begin(pool).await?;
+        let item = find_in::<T>(id, &mut tx).await?;
+        tx.commit().await?;
+        Ok(item)
+    })
+    .await
+}
+
+/// `find` inside the transaction `tx`
+pub async fn find_in<T: Resource>(id: i32, tx: &mut PgTx) -> anyhow::Result<T> {
+    let statements = Statements::of::<T>();
+    let item = sqlx::query(&statements.find)
         .bind(id)
-        .map(|row: PgRow| Person {
-            id: row.get(0),
-            first_name: row.get(1),
-            last_name: row.get(2),
-        })
-        .fetch_one(&mut tx)
+        .map(|row: PgRow| T::from_row(&row))
+        .fetch_one(tx)
         .await?;
+    record_rows(1);
+    Ok(item)
+}
 
-    tx.commit().await?;
-
-    Ok(Person {
-        id: rec.id,
-        first_name: rec.first_name,
-        last_name: rec.last_name,
+///
+/// The number of rows of a resource and their latest update,
+/// none when the table does not keep `UPDATED_AT`
+///
+pub async fn version<T: Resource>(pool: &PgPool) -> anyhow::Result<Option<ListVersion>> {
+    let statement = match Statements::of::<T>().version {
+        Some(statement) => statement,
+        None => return Ok(None),
+    };
+    run(&format!("version_{}", T::PLURAL), async {
+        let mut tx = begin(pool).await?;
+        let version = sqlx::query(&statement)
+            .map(|row: PgRow| ListVersion {
+                count: row.get(0),
+                updated_at: row.get(1),
+            })
+            .fetch_one(&mut tx)
+            .await?;
+        tx.commit().await?;
+        record_rows(1);
+        Ok(Some(version))
     })
+    .await
 }
 
-pub async fn add_person(pool: &PgPool, pers: InsertablePerson) -> anyhow::Result<Person> {
-    let mut tx = pool.begin().await?;
-    let rec = sqlx::query(
-        "INSERT INTO persons (first_name, last_name)
-                VALUES ( $1, $2 )
-                RETURNING id, first_name, last_name;",
-    )
-    .bind(&pers.first_name)
-    .bind(&pers.last_name)
-    .map(|row: PgRow| Person {
-        id: row.get(0),
-        first_name: row.get(1),
-        last_name: row.get(2),
+pub async fn add<T: Resource>(pool: &PgPool, item: T::Insertable) -> anyhow::Result<T> {
+    run(&format!("add_{}", T::NAME), async {
+        let mut tx = begin(pool).await?;
+        let item = add_in::<T>(&mut tx, item).await?;
+        tx.commit().await?;
+
+        log::debug!("{} added : {}", T::NAME, item.id());
+        Ok(item)
     })
-    .fetch_one(&mut tx)
-    .await?;
-    tx.commit().await?;
+    .await
+}
+
+/// `add` inside the transaction `tx`
+pub async fn add_in<T: Resource>(tx: &mut PgTx, item: T::Insertable) -> anyhow::Result<T> {
+    let item = insert_in::<T>(&mut *tx, item).await?;
+    webhooks::enqueue_in(tx, &format!("{}.created", T::NAME), &item).await?;
+    Ok(item)
+}
 
-    log::debug!("person added : {:?}", &rec);
-    Ok(rec)
+/// `add_in` without the webhooks, for the rows loaded rather than created
+pub async fn insert_in<T: Resource>(tx: &mut PgTx, item: T::Insertable) -> anyhow::Result<T> {
+    let statements = Statements::of::<T>();
+    let item = bind_values!(sqlx::query(&statements.insert), T::values(&item))
+        .map(|row: PgRow| T::from_row(&row))
+        .fetch_one(&mut *tx)
+        .await?;
+    record_rows(1);
+    cache::notify_in(tx, T::TABLE, item.id()).await?;
+    Ok(item)
 }
 
-pub async fn update_person(
+pub async fn update<T: Resource>(
     id: i32,
-    update_person: InsertablePerson,
+    item: T::Insertable,
     pool: &PgPool,
-) -> anyhow::Result<Person> {
-    let mut tx = pool.begin().await.unwrap();
-    let person = sqlx::query(
-        "UPDATE persons \
-                                        SET first_name = $1, \
-                                        last_name = $2 \
-                                        WHERE id = $3 \
-                                        RETURNING id, first_name, last_name;",
-    )
-    .bind(&update_person.first_name)
-    .bind(&update_person.last_name)
-    .bind(id)
-    .map(|row: PgRow| Person {
-        id: row.get(0),
-        first_name: row.get(1),
-        last_name: row.get(2),
+) -> anyhow::Result<T> {
+    run(&format!("update_{}", T::NAME), async {
+        let mut tx = begin(pool).await?;
+        let item = update_in::<T>(id, item, &mut tx).await?;
+        tx.commit().await?;
+        Ok(item)
     })
-    .fetch_one(&mut tx)
-    .await?;
+    .await
+}
 
-    tx.commit().await?;
-    Ok(person)
+/// `update` inside the transaction `tx`
+pub async fn update_in<T: Resource>(
+    id: i32,
+    item: T::Insertable,
+    tx: &mut PgTx,
+) -> anyhow::Result<T> {
+    let statements = Statements::of::<T>();
+    let item = bind_values!(sqlx::query(&statements.update), T::values(&item))
+        .bind(id)
+        .map(|row: PgRow| T::from_row(&row))
+        .fetch_one(&mut *tx)
+        .await?;
+    record_rows(1);
+    cache::notify_in(&mut *tx, T::TABLE, id).await?;
+    webhooks::enqueue_in(tx, &format!("{}.updated", T::NAME), &item).await?;
+    Ok(item)
 }
 
-pub async fn delete_person(id: i32, pool: &PgPool) -> anyhow::Result<i32> {
-    let mut tx = pool.begin().await?;
-    let res = sqlx::query("DELETE FROM persons WHERE id = $1")
+/// Returns the number of rows deleted
+pub async fn delete<T: Resource>(id: i32, pool: &PgPool) -> anyhow::Result<i32> {
+    run(&format!("delete_{}", T::NAME), async {
+        let mut tx = begin(pool).await?;
+        let deleted = delete_in::<T>(id, &mut tx).await?;
+        tx.commit().await?;
+        Ok(deleted)
+    })
+    .await
+}
+
+/// `delete` inside the transaction `tx`
+pub async fn delete_in<T: Resource>(id: i32, tx: &mut PgTx) -> anyhow::Result<i32> {
+    let statements = Statements::of::<T>();
+    let deleted: Vec<T> = sqlx::query(&statements.delete)
         .bind(id)
-        .execute(&mut tx)
+        .map(|row: PgRow| T::from_row(&row))
+        .fetch_all(&mut *tx)
+        .await?;
+    record_rows(deleted.len() as u64);
+    for item in &deleted {
+        cache::notify_in(&mut *tx, T::TABLE, item.id()).await?;
+        webhooks::enqueue_in(&mut *tx, &format!("{}.deleted", T::NAME), item).await?;
+    }
+    Ok(deleted.len() as i32)
+}
+
+///
+/// True when the table of a resource has no row, without reading them
+///
+pub async fn is_empty_in<T: Resource>(tx: &mut PgTx) -> anyhow::Result<bool> {
+    let exists: bool = sqlx::query(&format!("SELECT EXISTS (SELECT 1 FROM {});", T::TABLE))
+        .map(|row: PgRow| row.get(0))
+        .fetch_one(tx)
+        .await?;
+    Ok(!exists)
+}
+
+///
+/// Empties the table of a resource and restarts its ids at 1,
+/// forgetting the ids merged into its rows
+///
+pub async fn reset_in<T: Resource>(tx: &mut PgTx) -> anyhow::Result<()> {
+    sqlx::query(&format!("TRUNCATE {} RESTART IDENTITY;", T::TABLE))
+        .execute(&mut *tx)
         .await?;
+    sqlx::query("DELETE FROM merges WHERE resource = $1;")
+        .bind(T::TABLE)
+        .execute(&mut *tx)
+        .await?;
+    cache::notify_table_in(tx, T::TABLE).await?;
+    Ok(())
+}
+
+///
+/// The rows whose `MATCH_ON` text is at least `threshold` similar to the one of `item`,
+/// the most similar first
+///
+pub async fn similar<T: Resource>(
+    item: &T::Insertable,
+    threshold: f32,
+    pool: &PgPool,
+) -> anyhow::Result<Vec<(T, f32)>> {
+    run(&format!("similar_{}", T::PLURAL), async {
+        let mut tx = begin(pool).await?;
+        let similar = similar_in::<T>(item, threshold, &mut tx).await?;
+        tx.commit().await?;
+        Ok(similar)
+    })
+    .await
+}
+
+/// `similar` inside the transaction `tx`
+pub async fn similar_in<T: Resource>(
+    item: &T::Insertable,
+    threshold: f32,
+    tx: &mut PgTx,
+) -> anyhow::Result<Vec<(T, f32)>> {
+    let statement = match Statements::of::<T>().similar {
+        Some(statement) => statement,
+        None => return Ok(Vec::new()),
+    };
+    set_similarity_threshold_in(&mut *tx, threshold).await?;
+    let similar: Vec<(T, f32)> = sqlx::query(&statement)
+        .bind(T::match_text(item))
+        .bind(threshold)
+        .map(|row: PgRow| (T::from_row(&row), row.get(row.len() - 1)))
+        .fetch_all(tx)
+        .await?;
+    record_rows(similar.len() as u64);
+    Ok(similar)
+}
+
+///
+/// Makes `%` match from `threshold` until the end of the transaction `tx`,
+/// rather than from the default 0.3 of pg_trgm
+///
+async fn set_similarity_threshold_in(tx: &mut PgTx, threshold: f32) -> anyhow::Result<()> {
+    sqlx::query("SELECT set_config('pg_trgm.similarity_threshold', $1, true);")
+        .bind(threshold.to_string())
+        .execute(tx)
+        .await?;
+    Ok(())
+}
+
+/// How long the duplicates report may run before it is cancelled
+const DUPLICATES_TIMEOUT: &str = "10s";
+
+///
+/// The pairs of rows at least `threshold` similar, the most similar first
+/// each row is paired with its `DUPLICATES_PER_ROW` closest rows at most
+///
+pub async fn duplicates<T: Resource>(
+    threshold: f32,
+    pool: &PgPool,
+) -> anyhow::Result<Vec<Duplicate<T>>> {
+    run(&format!("duplicate_{}", T::PLURAL), async {
+        let mut tx = begin(pool).await?;
+        let duplicates = duplicates_in::<T>(threshold, &mut tx).await?;
+        tx.commit().await?;
+        Ok(duplicates)
+    })
+    .await
+}
 
-    tx.commit().await?;
-    let deleted = res as i32;
-    Ok(deleted)
+/// `duplicates` inside the transaction `tx`
+pub async fn duplicates_in<T: Resource>(
+    threshold: f32,
+    tx: &mut PgTx,
+) -> anyhow::Result<Vec<Duplicate<T>>> {
+    let statement = match Statements::of::<T>().duplicates {
+        Some(statement) => statement,
+        None => return Ok(Vec::new()),
+    };
+    set_similarity_threshold_in(&mut *tx, threshold).await?;
+    sqlx::query("SELECT set_config('statement_timeout', $1, true);")
+        .bind(DUPLICATES_TIMEOUT)
+        .execute(&mut *tx)
+        .await?;
+    let pairs: Vec<(i32, i32, f32)> = sqlx::query(&statement)
+        .bind(threshold)
+        .map(|row: PgRow| (row.get(0), row.get(1), row.get(2)))
+        .fetch_all(&mut *tx)
+        .await?;
+    record_rows(pairs.len() as u64);
+    if pairs.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let items: HashMap<i32, T> = list_in::<T>(tx)
+        .await?
+        .into_iter()
+        .map(|item| (item.id(), item))
+        .collect();
+    Ok(pairs
+        .into_iter()
+        .filter_map(|(first, second, similarity)| {
+            Some(Duplicate {
+                first: items.get(&first)?.clone(),
+                second: items.get(&second)?.clone(),
+                similarity,
+            })
+        })
+        .collect())
+}
+
+///
+/// Merges the row `remove` into the row `keep`:
+/// `remove` is deleted and its id redirected to `keep` in the `merges` table,
+/// along with the ids already merged into `remove`
+///
+pub async fn merge<T: Resource>(keep: i32, remove: i32, pool: &PgPool) -> anyhow::Result<T> {
+    run(&format!("merge_{}", T::PLURAL), async {
+        let mut tx = begin(pool).await?;
+        let kept = merge_in::<T>(keep, remove, &mut tx).await?;
+        tx.commit().await?;
+        Ok(kept)
+    })
+    .await
+}
+
+/// `merge` inside the transaction `tx`
+pub async fn merge_in<T: Resource>(keep: i32, remove: i32, tx: &mut PgTx) -> anyhow::Result<T> {
+    if keep == remove {
+        bail!("cannot merge {} {} into itself", T::NAME, keep);
+    }
+    let kept = find_in::<T>(keep, &mut *tx).await?;
+    sqlx::query("UPDATE merges SET into_id = $3 WHERE resource = $1 AND into_id = $2;")
+        .bind(T::TABLE)
+        .bind(remove)
+        .bind(keep)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(
+        "INSERT INTO merges (resource, merged_id, into_id)
+                VALUES ( $1, $2, $3 )
+                ON CONFLICT (resource, merged_id) DO UPDATE SET into_id = EXCLUDED.into_id;",
+    )
+    .bind(T::TABLE)
+    .bind(remove)
+    .bind(keep)
+    .execute(&mut *tx)
+    .await?;
+    if delete_in::<T>(remove, tx).await? == 0 {
+        bail!("no {} with id {}", T::NAME, remove);
+    }
+    Ok(kept)
+}
+
+///
+/// The row a merged id now refers to, none when `id` was never merged
+///
+pub async fn merged_into<T: Resource>(id: i32, pool: &PgPool) -> anyhow::Result<Option<i32>> {
+    run(&format!("merged_into_{}", T::NAME), async {
+        let mut tx = begin(pool).await?;
+        let into = merged_into_in::<T>(id, &mut tx).await?;
+        tx.commit().await?;
+        Ok(into)
+    })
+    .await
+}
+
+/// `merged_into` inside the transaction `tx`
+pub async fn merged_into_in<T: Resource>(id: i32, tx: &mut PgTx) -> anyhow::Result<Option<i32>> {
+    let into = sqlx::query("SELECT into_id FROM merges WHERE resource = $1 AND merged_id = $2;")
+        .bind(T::TABLE)
+        .bind(id)
+        .map(|row: PgRow| row.get(0))
+        .fetch_optional(tx)
+        .await?;
+    Ok(into)
 }
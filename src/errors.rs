@@ -1,8 +1,11 @@
 // src/errors.rs
 
+use juniper::{FieldError, IntoFieldError};
 use thiserror::Error;
 use sqlx::postgres::PgError;
 
+use crate::graphql;
+
 
 #[derive(Error, Debug)]
 pub enum CustError {
@@ -14,4 +17,20 @@ pub enum CustError {
     PgSqlxError(#[from] sqlx::postgres::PgError),
 }
 
-impl warp::reject::Reject for CustError {}
\ No newline at end of file
+impl warp::reject::Reject for CustError {}
+
+///
+/// The GraphQL error of a failed query
+/// the code tells what failed, the details are only logged
+///
+impl IntoFieldError for CustError {
+    fn into_field_error(self) -> FieldError {
+        tracing::error!("GRAPHQL : {}", self);
+        let code = match self {
+            CustError::DBPoolError(_) => "DB_POOL_ERROR",
+            CustError::DBQueryError(_) => "DB_QUERY_ERROR",
+            CustError::PgSqlxError(_) => "PG_SQLX_ERROR",
+        };
+        FieldError::new("could not execute the request", graphql::extensions(code, None))
+    }
+}
@@ -1,49 +1,368 @@
 // src/filters
 
+use std::path::PathBuf;
+use std::time::Duration;
+
 use warp::{Filter, Reply,};
 use sqlx::PgPool;
+use tera::Context;
 use warp::filters::BoxedFilter;
+use warp::http::Method;
+use warp::reply::Response;
 
+use crate::changes::ChangeFeed;
+use crate::compression;
+use crate::conditional;
+use crate::config::{Config, CorsConfig};
+use crate::graphql::{self, GraphQLContext};
 use crate::handlers;
-use crate::models::InsertablePerson;
+use crate::metrics::instrument;
+use crate::models::{InsertablePerson, Person};
+use crate::rate_limit::{Access, RateLimiter};
+use crate::repository::{PersonRepository, Repository};
+use crate::resource::{DuplicatesQuery, Resource};
+
 
+///
+/// The whole API
+/// the probes, the metrics, the docs, the static files and the person routes,
+/// every rejection answered with an `ErrorMessage`
+///
+pub fn api<R: PersonRepository>(
+    pool: PgPool,
+    repo: R,
+    feed: ChangeFeed,
+    config: &Config,
+) -> BoxedFilter<(impl Reply,)> {
+    routes(pool, repo, feed, config, "")
+        .recover(handlers::handle_rejection)
+        .boxed()
+}
+
+///
+/// The routes of the API, without any recovery of the rejections
+/// the links of the pages and the redirections start with `base_path`,
+/// the path they are mounted under, `""` at the root
+///
+pub fn routes<R: PersonRepository>(
+    pool: PgPool,
+    repo: R,
+    feed: ChangeFeed,
+    config: &Config,
+    base_path: &str,
+) -> BoxedFilter<(impl Reply,)> {
+    let limiter = RateLimiter::new(config.rate_limit.clone());
+    health_filters(pool.clone(), config.readiness_timeout)
+        .or(metrics_filter(pool.clone()))
+        .or(api_docs(base_path))
+        .or(with_cors(&config.cors, person_events(feed, &limiter)))
+        .or(compression::compress(
+            &config.compression,
+            static_files()
+                .or(with_cors(&config.cors, graphql_filters(pool.clone(), config.graphiql, base_path, &limiter)))
+                .or(with_cors(&config.cors, webhook_filters(pool.clone(), config.webhooks.allow_private_targets, &limiter)))
+                .or(with_cors(&config.cors, admin_filters(pool, config.jobs.export_dir.clone(), base_path, &limiter)))
+                .or(with_cors(&config.cors, person_filters(repo, base_path, limiter)))
+                .boxed(),
+        ))
+        .boxed()
+}
 
 ///
 /// Main Filter
 /// function that takes all filters
 ///
-pub async fn person_filters(pool: PgPool) -> BoxedFilter<(impl Reply,)> {
-    handle_routes(pool.clone())
-        .or(add_routes(pool.clone()))
-        .or(page_list(pool.clone()))
-        .or(page_home()).boxed()
+pub fn person_filters<R: PersonRepository>(repo: R, base_path: &str, limiter: RateLimiter) -> BoxedFilter<(impl Reply,)> {
+    resource_filters::<Person, R>(repo.clone(), base_path, &limiter)
+        // after the resource routes, it only sees the ids they did not find
+        .or(duplicate_filters(repo, base_path, &limiter))
+        .or(instrument(Method::GET, "/", page_home(base_path, &limiter)))
+        .boxed()
 }
 
 ///
-/// Filter for the different add routes
-/// the first route shows the add page
-/// the second route handles the data to add a person to the DB
+/// Filter for the duplicate persons
+/// the report, the merge, and the redirection of the merged ids
 ///
-fn add_routes(pool: PgPool)-> BoxedFilter<(impl Reply,)> {
-    page_add()
-        .or(add_person(pool.clone()))
+pub fn duplicate_filters<R: PersonRepository>(repo: R, base_path: &str, limiter: &RateLimiter) -> BoxedFilter<(Response,)> {
+    let report = warp::get()
+        .and(warp::path("persons"))
+        .and(warp::path("duplicates"))
+        .and(warp::path::end())
+        .and(limiter.limit(Access::Read))
+        .and(warp::query::<DuplicatesQuery>())
+        .and(with_repo(repo.clone()))
+        .and_then(handlers::duplicates_hdler::<Person, R>);
+
+    let merge = warp::post()
+        .and(warp::path("persons"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("merge"))
+        .and(warp::path::end())
+        .and(limiter.limit(Access::Write))
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
+        .and(with_repo(repo.clone()))
+        .and_then(handlers::merge_hdler::<Person, R>);
+
+    let merged = warp::get()
+        .and(item_path::<Person>())
+        .and(warp::path::end())
+        .and(limiter.limit(Access::Read))
+        .and(with_base_path(base_path))
+        .and(with_repo(repo))
+        .and_then(handlers::merged_hdler::<Person, R>);
+
+    instrument(Method::GET, "/persons/duplicates", report)
+        .or(instrument(Method::POST, "/persons/{id}/merge", merge))
+        .unify()
+        .or(instrument(Method::GET, Person::ITEM_ROUTE, merged))
+        .unify()
         .boxed()
 }
 
-fn handle_routes(pool: PgPool) -> BoxedFilter<(impl Reply,)> {
-    page_modify(pool.clone())
-        .or(update_person(pool.clone()))
-        .or(delete_person(pool.clone()))
+///
+/// The CRUD routes of a resource, at the route templates it declares
+/// list, add page, add, modify page, update and delete
+///
+pub fn resource_filters<T: Resource, R: Repository<T>>(repo: R, base_path: &str, limiter: &RateLimiter) -> BoxedFilter<(Response,)> {
+    instrument(Method::GET, T::ITEM_ROUTE, page_modify::<T, R>(repo.clone(), base_path, limiter))
+        .or(instrument(Method::PUT, T::ITEM_ROUTE, update::<T, R>(repo.clone(), base_path, limiter)))
+        .unify()
+        .or(instrument(Method::DELETE, T::ITEM_ROUTE, delete::<T, R>(repo.clone(), base_path, limiter)))
+        .unify()
+        .or(instrument(Method::GET, T::ADD_ROUTE, page_add::<T>(base_path, limiter)))
+        .unify()
+        .or(instrument(Method::POST, T::ADD_ROUTE, add::<T, R>(repo.clone(), base_path, limiter)))
+        .unify()
+        .or(instrument(Method::GET, T::LIST_ROUTE, page_list::<T, R>(repo, base_path, limiter)))
+        .unify()
         .boxed()
 }
 
+///
+/// Filter streaming the changes of the persons
+/// GET Method, kept out of the compression which would hold the stream back
+///
+pub fn person_events(feed: ChangeFeed, limiter: &RateLimiter) -> BoxedFilter<(Response,)> {
+    let events = warp::get()
+        .and(warp::path("persons"))
+        .and(warp::path("events"))
+        .and(warp::path::end())
+        .and(limiter.limit(Access::Read))
+        .and(warp::sse::last_event_id::<String>())
+        .and(warp::any().map(move || feed.clone()))
+        .and_then(handlers::person_events_hdler);
+
+    instrument(Method::GET, "/persons/events", events)
+}
+
+///
+/// Filter for the GraphQL endpoint, queries by GET or POST, mutations by POST only,
+/// and the GraphiQL explorer when it is enabled
+///
+pub fn graphql_filters(pool: PgPool, graphiql: bool, base_path: &str, limiter: &RateLimiter) -> BoxedFilter<(Response,)> {
+    let context = warp::any()
+        .map(move || GraphQLContext { pool: pool.clone() })
+        .boxed();
+    // a GET is answered by the schema without the mutations
+    let read_only = juniper_warp::make_graphql_filter(graphql::read_only_schema(), context.clone());
+    let graphql = juniper_warp::make_graphql_filter(graphql::schema(), context);
+
+    let get = warp::get()
+        .and(warp::path("graphql"))
+        .and(warp::path::end())
+        .and(limiter.limit(Access::Read))
+        .and(read_only);
+
+    // a POST may carry mutations
+    let post = warp::post()
+        .and(warp::path("graphql"))
+        .and(warp::path::end())
+        .and(limiter.limit(Access::Write))
+        .and(graphql);
+
+    let routes = instrument(Method::GET, "/graphql", get)
+        .or(instrument(Method::POST, "/graphql", post))
+        .unify();
+    if !graphiql {
+        return routes.boxed();
+    }
+
+    let explorer = warp::get()
+        .and(warp::path("graphiql"))
+        .and(warp::path::end())
+        .and(with_base_path(base_path))
+        .and_then(handlers::graphiql_hdler);
+    routes
+        .or(instrument(Method::GET, "/graphiql", explorer))
+        .unify()
+        .boxed()
+}
+
+///
+/// Filter for the webhook subscriptions API
+/// list, create, delete and the deliveries of one subscription
+/// the targets may be private addresses only when `allow_private`
+///
+pub fn webhook_filters(pool: PgPool, allow_private: bool, limiter: &RateLimiter) -> BoxedFilter<(Response,)> {
+    let list = warp::get()
+        .and(warp::path("webhooks"))
+        .and(warp::path::end())
+        .and(limiter.limit(Access::Read))
+        .and(with_db(pool.clone()))
+        .and_then(handlers::list_webhooks_hdler);
+
+    let create = warp::post()
+        .and(warp::path("webhooks"))
+        .and(warp::path::end())
+        .and(limiter.limit(Access::Write))
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
+        .and(warp::any().map(move || allow_private))
+        .and(with_db(pool.clone()))
+        .and_then(handlers::create_webhook_hdler);
+
+    let delete = warp::delete()
+        .and(warp::path("webhooks"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path::end())
+        .and(limiter.limit(Access::Write))
+        .and(with_db(pool.clone()))
+        .and_then(handlers::delete_webhook_hdler);
+
+    let deliveries = warp::get()
+        .and(warp::path("webhooks"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("deliveries"))
+        .and(warp::path::end())
+        .and(limiter.limit(Access::Read))
+        .and(with_db(pool))
+        .and_then(handlers::webhook_deliveries_hdler);
+
+    instrument(Method::GET, "/webhooks", list)
+        .or(instrument(Method::POST, "/webhooks", create))
+        .unify()
+        .or(instrument(Method::DELETE, "/webhooks/{id}", delete))
+        .unify()
+        .or(instrument(Method::GET, "/webhooks/{id}/deliveries", deliveries))
+        .unify()
+        .boxed()
+}
+
+///
+/// Filter for the admin pages
+/// the background jobs, and queuing an export of the persons
+///
+pub fn admin_filters(pool: PgPool, export_dir: PathBuf, base_path: &str, limiter: &RateLimiter) -> BoxedFilter<(Response,)> {
+    let jobs = warp::get()
+        .and(warp::path("admin"))
+        .and(warp::path("jobs"))
+        .and(warp::path::end())
+        .and(limiter.limit(Access::Read))
+        .and(with_page_context(base_path))
+        .and(with_db(pool.clone()))
+        .and_then(handlers::admin_jobs_hdler);
+
+    let export = warp::post()
+        .and(warp::path("admin"))
+        .and(warp::path("jobs"))
+        .and(warp::path("export"))
+        .and(warp::path::end())
+        .and(limiter.limit(Access::Write))
+        .and(warp::any().map(move || export_dir.clone()))
+        .and(with_base_path(base_path))
+        .and(with_db(pool))
+        .and_then(handlers::export_persons_hdler);
+
+    instrument(Method::GET, "/admin/jobs", jobs)
+        .or(instrument(Method::POST, "/admin/jobs/export", export))
+        .unify()
+        .boxed()
+}
+
+///
+/// Filter for the health probes
+/// `/healthz` tells the process is up,
+/// `/readyz` tells the database can be used
+///
+pub fn health_filters(pool: PgPool, timeout: Duration) -> BoxedFilter<(impl Reply,)> {
+    let liveness = warp::get()
+        .and(warp::path("healthz"))
+        .and(warp::path::end())
+        .and_then(handlers::healthz_hdler);
+
+    let readiness = warp::get()
+        .and(warp::path("readyz"))
+        .and(warp::path::end())
+        .and(with_db(pool))
+        .and(warp::any().map(move || timeout))
+        .and_then(handlers::readyz_hdler);
+
+    instrument(Method::GET, "/healthz", liveness)
+        .or(instrument(Method::GET, "/readyz", readiness))
+        .boxed()
+}
+
+///
+/// Filter serving the static assets
+/// GET Method
+///
+pub fn static_files() -> BoxedFilter<(impl Reply,)> {
+    let assets = warp::get()
+        .and(warp::path("static"))
+        .and(warp::path::tail())
+        .and(warp::header::optional::<String>("if-none-match"))
+        .and_then(handlers::static_asset_hdler);
+
+    instrument(Method::GET, "/static/{file}", assets)
+}
+
+///
+/// Filter exposing the Prometheus metrics
+/// GET Method
+///
+pub fn metrics_filter(pool: PgPool) -> BoxedFilter<(impl Reply,)> {
+    let metrics = warp::get()
+        .and(warp::path("metrics"))
+        .and(warp::path::end())
+        .and(with_db(pool))
+        .and_then(handlers::metrics_hdler);
+
+    instrument(Method::GET, "/metrics", metrics)
+}
+
+///
+/// Filter for the API documentation
+/// `/openapi.json` serves the OpenAPI document,
+/// `/docs` the Swagger UI page browsing it
+///
+pub fn api_docs(base_path: &str) -> BoxedFilter<(impl Reply,)> {
+    let spec = warp::get()
+        .and(warp::path("openapi.json"))
+        .and(warp::path::end())
+        .and(with_base_path(base_path))
+        .and_then(handlers::openapi_hdler);
+
+    let explorer = warp::get()
+        .and(warp::path("docs"))
+        .and(warp::path::end())
+        .and(with_page_context(base_path))
+        .and_then(handlers::api_docs_hdler);
+
+    instrument(Method::GET, "/openapi.json", spec)
+        .or(instrument(Method::GET, "/docs", explorer))
+        .boxed()
+}
 
 ///
 /// Filter to display the Home Page
 ///
-fn page_home() -> BoxedFilter<(impl Reply,)> {
+fn page_home(base_path: &str, limiter: &RateLimiter) -> BoxedFilter<(impl Reply,)> {
     warp::get()
         .and(warp::path::end())
+        .and(limiter.limit(Access::Read))
+        .and(with_page_context(base_path))
         .and_then(handlers::page_home_hdler)
         .boxed()
 }
@@ -51,36 +370,43 @@ fn page_home() -> BoxedFilter<(impl Reply,)> {
 /// Filter to display the list page
 /// GET Method
 ///
-fn page_list(pool: PgPool) -> BoxedFilter<(impl Reply,)> {
+fn page_list<T: Resource, R: Repository<T>>(repo: R, base_path: &str, limiter: &RateLimiter) -> BoxedFilter<(impl Reply,)> {
     warp::get()
-        .and(warp::path("persons"))
+        .and(path_of(T::LIST_ROUTE))
         .and(warp::path::end())
-        .and(with_db(pool.clone()))
-        .and_then(handlers::list_persons_hdler)
+        .and(limiter.limit(Access::Read))
+        .and(conditional::preconditions())
+        .and(with_page_context(base_path))
+        .and(with_repo(repo))
+        .and_then(handlers::list_hdler::<T, R>)
         .boxed()
 }
 ///
 /// Filter to display the add page
 /// GET Method
 ///
-fn page_add() -> BoxedFilter<(impl Reply,)> {
+fn page_add<T: Resource>(base_path: &str, limiter: &RateLimiter) -> BoxedFilter<(impl Reply,)> {
     warp::get()
-        .and(warp::path("add"))
+        .and(path_of(T::ADD_ROUTE))
         .and(warp::path::end())
-        .and_then(handlers::page_add_hdler)
+        .and(limiter.limit(Access::Read))
+        .and(with_page_context(base_path))
+        .and_then(handlers::page_add_hdler::<T>)
         .boxed()
 }
 ///
 /// Filter to display the modify page
 /// GET Method
 ///
-fn page_modify(pool: PgPool) -> BoxedFilter<(impl Reply,)>{
+fn page_modify<T: Resource, R: Repository<T>>(repo: R, base_path: &str, limiter: &RateLimiter) -> BoxedFilter<(impl Reply,)> {
     warp::get()
-        .and(warp::path("persons"))
-        .and(warp::path::param::<i32>())
+        .and(item_path::<T>())
         .and(warp::path::end())
-        .and(with_db(pool.clone()))
-        .and_then(handlers::find_person_by_id_hdler)
+        .and(limiter.limit(Access::Read))
+        .and(conditional::preconditions())
+        .and(with_page_context(base_path))
+        .and(with_repo(repo))
+        .and_then(handlers::find_hdler::<T, R>)
         .boxed()
 }
 
@@ -88,35 +414,40 @@ fn page_modify(pool: PgPool) -> BoxedFilter<(impl Reply,)>{
 /// Filter to treat adding
 /// POST Method
 ///
-fn add_person(pool: PgPool) -> BoxedFilter<(impl Reply,)> {
+fn add<T: Resource, R: Repository<T>>(repo: R, base_path: &str, limiter: &RateLimiter) -> BoxedFilter<(impl Reply,)> {
     warp::post()
-        .and(warp::path("add"))
-        .and(warp::body::form())
+        .and(path_of(T::ADD_ROUTE))
         .and(warp::path::end())
-        .and(with_db(pool.clone()))
-        .and_then(handlers::add_person_hdler)
+        .and(limiter.limit(Access::Write))
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::form())
+        .and(with_page_context(base_path))
+        .and(with_repo(repo))
+        .and_then(handlers::add_hdler::<T, R>)
         .boxed()
 }
 
-
-fn update_person(pool: PgPool) -> BoxedFilter<(impl Reply,)> {
+fn update<T: Resource, R: Repository<T>>(repo: R, base_path: &str, limiter: &RateLimiter) -> BoxedFilter<(impl Reply,)> {
     warp::put()
-        .and(warp::path("persons"))
-        .and(warp::path::param::<i32>())
-        .and(warp::body::form())
+        .and(item_path::<T>())
         .and(warp::path::end())
-        .and(with_db(pool.clone()))
-        .and_then(handlers::update_person_hdler)
+        .and(limiter.limit(Access::Write))
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::form())
+        .and(with_page_context(base_path))
+        .and(with_repo(repo))
+        .and_then(handlers::update_hdler::<T, R>)
         .boxed()
 }
 
-fn delete_person(pool: PgPool) -> BoxedFilter<(impl Reply,)> {
+fn delete<T: Resource, R: Repository<T>>(repo: R, base_path: &str, limiter: &RateLimiter) -> BoxedFilter<(impl Reply,)> {
     warp::delete()
-        .and(warp::path("persons"))
-        .and(warp::path::param::<i32>())
+        .and(item_path::<T>())
         .and(warp::path::end())
-        .and(with_db(pool.clone())).boxed()
-        .and_then(handlers::delete_person_hdler)
+        .and(limiter.limit(Access::Write))
+        .and(with_page_context(base_path))
+        .and(with_repo(repo))
+        .and_then(handlers::delete_hdler::<T, R>)
         .boxed()
 }
 
@@ -125,6 +456,165 @@ fn delete_person(pool: PgPool) -> BoxedFilter<(impl Reply,)> {
 // Helper Filters
 //******************************************************
 
+///
+/// Applies the CORS policy to the cross-origin requests of a filter
+/// the same-origin requests (our own pages posting forms, the fetch calls
+/// of the modify page) also send an `Origin` header, they go around the policy
+/// without any allowed origin, there is no policy and no CORS header at all
+///
+pub fn with_cors<R: Reply + 'static>(
+    config: &CorsConfig,
+    filter: BoxedFilter<(R,)>,
+) -> BoxedFilter<(Response,)> {
+    if config.allowed_origins.is_empty() {
+        return filter.map(Reply::into_response).boxed();
+    }
+    let same_origin = origin_matches_host(true, config.public_origin.clone())
+        .and(filter.clone())
+        .map(Reply::into_response);
+    let cross_origin = origin_matches_host(false, config.public_origin.clone())
+        .and(filter.with(cors(config)))
+        .map(Reply::into_response);
+    same_origin.or(cross_origin).unify().boxed()
+}
+
+///
+/// The CORS policy applied to the API routes
+/// the preflight requests are answered by the wrapper itself,
+/// so PUT and DELETE need to be in the allowed methods
+///
+fn cors(config: &CorsConfig) -> warp::cors::Cors {
+    let mut cors = warp::cors()
+        .allow_methods(config.allowed_methods.iter().map(String::as_str))
+        .allow_headers(config.allowed_headers.iter().map(String::as_str))
+        .expose_headers(vec![
+            "x-request-id",
+            "retry-after",
+            "ratelimit-limit",
+            "ratelimit-remaining",
+            "ratelimit-reset",
+        ])
+        .allow_credentials(config.allow_credentials)
+        .max_age(Duration::from_secs(config.max_age));
+
+    if config.allowed_origins.iter().any(|origin| origin == "*") {
+        if config.allow_credentials {
+            tracing::warn!("CORS : credentials are not sent by browsers to any origin (*)");
+        }
+        cors = cors.allow_any_origin();
+    } else {
+        cors = cors.allow_origins(config.allowed_origins.iter().map(String::as_str));
+    }
+    cors.build()
+}
+
+///
+/// Passes the requests without `Origin` header or whose origin is our own
+/// when `same` is true, the other ones when `same` is false
+///
+fn origin_matches_host(same: bool, public_origin: Option<String>) -> BoxedFilter<()> {
+    warp::header::optional::<String>("origin")
+        .and(warp::header::optional::<String>("x-forwarded-host"))
+        .and(warp::header::optional::<String>("host"))
+        .and_then(
+            move |origin: Option<String>, forwarded: Option<String>, host: Option<String>| {
+                let is_same = match origin {
+                    None => true,
+                    Some(origin) => is_same_origin(
+                        &origin,
+                        public_origin.as_deref(),
+                        forwarded.as_deref(),
+                        host.as_deref(),
+                    ),
+                };
+                async move {
+                    if is_same == same {
+                        Ok(())
+                    } else {
+                        Err(warp::reject::not_found())
+                    }
+                }
+            },
+        )
+        .untuple_one()
+        .boxed()
+}
+
+///
+/// True when `origin` is the configured public origin,
+/// or names the host the client asked for: the one a proxy forwarded, or else `Host`
+///
+pub(crate) fn is_same_origin(
+    origin: &str,
+    public_origin: Option<&str>,
+    forwarded_host: Option<&str>,
+    host: Option<&str>,
+) -> bool {
+    if let Some(public_origin) = public_origin {
+        if origin.trim_end_matches('/') == public_origin.trim_end_matches('/') {
+            return true;
+        }
+    }
+    // a proxy chain lists the hosts, the first is the one of the client
+    let host = forwarded_host
+        .and_then(|hosts| hosts.split(',').next())
+        .map(str::trim)
+        .or(host);
+    match host {
+        Some(host) => origin.splitn(2, "://").nth(1) == Some(host),
+        None => false,
+    }
+}
+
+///
+/// Matches the static segments of a path, `/people/persons` is `people` then `persons`
+///
+pub fn path_of(path: &str) -> BoxedFilter<()> {
+    path.split('/')
+        .filter(|segment| !segment.is_empty())
+        .fold(warp::any().boxed(), |prefix, segment| {
+            prefix.and(warp::path(segment.to_string())).boxed()
+        })
+}
+
+///
+/// Matches the item route of a resource and extracts its id
+///
+fn item_path<T: Resource>() -> BoxedFilter<(i32,)> {
+    path_of(T::ITEM_ROUTE.trim_end_matches("/{id}"))
+        .and(warp::path::param::<i32>())
+        .boxed()
+}
+
+fn with_repo<R: Clone + Send + Sync + 'static>(repo: R) -> BoxedFilter<(R,)> {
+    warp::any()
+        .map(move || repo.clone())
+        .boxed()
+}
+
+///
+/// The path the routes are mounted under, `""` at the root
+///
+fn with_base_path(base_path: &str) -> BoxedFilter<(String,)> {
+    let base_path = base_path.to_string();
+    warp::any()
+        .map(move || base_path.clone())
+        .boxed()
+}
+
+///
+/// The Tera context of a page, holding the `base_path` its links start with
+///
+fn with_page_context(base_path: &str) -> BoxedFilter<(Context,)> {
+    with_base_path(base_path)
+        .map(|base_path: String| {
+            let mut ctx = Context::new();
+            ctx.insert("base_path", &base_path);
+            ctx
+        })
+        .boxed()
+}
+
 fn with_db(pool: PgPool) -> BoxedFilter<(PgPool,)> {
     warp::any()
         .map(move || pool.clone())
@@ -0,0 +1,230 @@
+// src/graphql.rs
+
+use juniper::{
+    graphql_object, EmptyMutation, EmptySubscription, FieldError, FieldResult, GraphQLInputObject,
+    GraphQLObject, IntoFieldError, Object, RootNode, Value,
+};
+use schemars::JsonSchema;
+use sqlx::PgPool;
+
+use crate::db;
+use crate::errors::CustError;
+use crate::models::{InsertablePerson, Person};
+use crate::resource::{Invalid, Resource};
+
+/// Persons returned by `persons` when `first` is not given
+const DEFAULT_PAGE: i32 = 20;
+/// The most persons `persons` returns at once
+const MAX_PAGE: i32 = 100;
+
+///
+/// What the resolvers share, built for every request
+///
+pub struct GraphQLContext {
+    pub pool: PgPool,
+}
+
+impl juniper::Context for GraphQLContext {}
+
+/// A person of the directory
+#[graphql_object(context = GraphQLContext)]
+impl Person {
+    fn id(&self) -> i32 {
+        self.id
+    }
+
+    fn first_name(&self) -> &str {
+        &self.first_name
+    }
+
+    fn last_name(&self) -> &str {
+        &self.last_name
+    }
+}
+
+/// The names of a person to create or update
+#[derive(GraphQLInputObject, Debug, Clone)]
+pub struct PersonInput {
+    pub first_name: String,
+    pub last_name: String,
+}
+
+impl From<PersonInput> for InsertablePerson {
+    fn from(input: PersonInput) -> InsertablePerson {
+        InsertablePerson {
+            first_name: input.first_name,
+            last_name: input.last_name,
+        }
+    }
+}
+
+/// Keeps the persons whose names contain these texts, whatever the case
+#[derive(GraphQLInputObject, Debug, Clone, Default)]
+pub struct PersonFilter {
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+}
+
+impl PersonFilter {
+    /// The columns to search and the texts they must contain
+    fn contains(&self) -> Vec<(&'static str, &str)> {
+        let mut contains = Vec::new();
+        if let Some(part) = &self.first_name {
+            contains.push(("first_name", part.as_str()));
+        }
+        if let Some(part) = &self.last_name {
+            contains.push(("last_name", part.as_str()));
+        }
+        contains
+    }
+}
+
+/// One page of the persons, ordered by id
+#[derive(GraphQLObject)]
+#[graphql(context = GraphQLContext)]
+pub struct PersonPage {
+    /// the persons matching the filter, on every page
+    pub total_count: i32,
+    pub has_next_page: bool,
+    pub items: Vec<Person>,
+}
+
+pub struct Query;
+
+#[graphql_object(context = GraphQLContext)]
+impl Query {
+    /// One person, null when there is none with this id
+    async fn person(context: &GraphQLContext, id: i32) -> FieldResult<Option<Person>> {
+        match db::find::<Person>(id, &context.pool).await {
+            Ok(person) => Ok(Some(person)),
+            Err(err) if is_row_not_found(&err) => Ok(None),
+            Err(err) => Err(field_error(err)),
+        }
+    }
+
+    /// The persons matching `filter`, `first` of them from `offset`
+    async fn persons(
+        context: &GraphQLContext,
+        filter: Option<PersonFilter>,
+        first: Option<i32>,
+        offset: Option<i32>,
+    ) -> FieldResult<PersonPage> {
+        let first = first.unwrap_or(DEFAULT_PAGE).max(0).min(MAX_PAGE) as i64;
+        let offset = offset.unwrap_or(0).max(0) as i64;
+        let filter = filter.unwrap_or_default();
+
+        let (items, total_count) =
+            db::search::<Person>(&filter.contains(), first, offset, &context.pool)
+                .await
+                .map_err(field_error)?;
+        Ok(PersonPage {
+            total_count: total_count as i32,
+            has_next_page: offset + (items.len() as i64) < total_count,
+            items,
+        })
+    }
+}
+
+pub struct Mutation;
+
+#[graphql_object(context = GraphQLContext)]
+impl Mutation {
+    async fn create_person(context: &GraphQLContext, input: PersonInput) -> FieldResult<Person> {
+        let person = InsertablePerson::from(input);
+        Person::validate(&person).map_err(invalid)?;
+        db::add::<Person>(&context.pool, person)
+            .await
+            .map_err(field_error)
+    }
+
+    /// The person updated, null when there is none with this id
+    async fn update_person(
+        context: &GraphQLContext,
+        id: i32,
+        input: PersonInput,
+    ) -> FieldResult<Option<Person>> {
+        let person = InsertablePerson::from(input);
+        Person::validate(&person).map_err(invalid)?;
+        match db::update::<Person>(id, person, &context.pool).await {
+            Ok(person) => Ok(Some(person)),
+            Err(err) if is_row_not_found(&err) => Ok(None),
+            Err(err) => Err(field_error(err)),
+        }
+    }
+
+    /// True when there was a person with this id
+    async fn delete_person(context: &GraphQLContext, id: i32) -> FieldResult<bool> {
+        let deleted = db::delete::<Person>(id, &context.pool)
+            .await
+            .map_err(field_error)?;
+        Ok(deleted > 0)
+    }
+}
+
+///
+/// The body of a POST to `/graphql`, for the OpenAPI document
+/// the requests themselves are read by `juniper_warp`
+///
+#[derive(JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphQLRequest {
+    pub query: String,
+    pub variables: Option<serde_json::Value>,
+    pub operation_name: Option<String>,
+}
+
+pub type Schema = RootNode<'static, Query, Mutation, EmptySubscription<GraphQLContext>>;
+
+pub fn schema() -> Schema {
+    Schema::new(Query, Mutation, EmptySubscription::new())
+}
+
+///
+/// The schema served by GET, without the mutations:
+/// a link or an image on another site must not change anything
+///
+pub type ReadOnlySchema =
+    RootNode<'static, Query, EmptyMutation<GraphQLContext>, EmptySubscription<GraphQLContext>>;
+
+pub fn read_only_schema() -> ReadOnlySchema {
+    ReadOnlySchema::new(Query, EmptyMutation::new(), EmptySubscription::new())
+}
+
+fn is_row_not_found(err: &anyhow::Error) -> bool {
+    match err.downcast_ref::<sqlx::Error>() {
+        Some(sqlx::Error::RowNotFound) => true,
+        _ => false,
+    }
+}
+
+///
+/// A database error as a GraphQL error, through `CustError`
+///
+fn field_error(err: anyhow::Error) -> FieldError {
+    match err.downcast::<sqlx::Error>() {
+        Ok(err) => CustError::from(err).into_field_error(),
+        Err(err) => {
+            tracing::error!("GRAPHQL : {}", err);
+            FieldError::new("internal error", extensions("INTERNAL_ERROR", None))
+        }
+    }
+}
+
+fn invalid(invalid: Invalid) -> FieldError {
+    FieldError::new(
+        format!("INVALID: {}", invalid),
+        extensions("INVALID", Some(invalid.field)),
+    )
+}
+
+///
+/// The `extensions` of an error: its code and the field at fault, if any
+///
+pub fn extensions(code: &str, field: Option<&str>) -> Value {
+    let mut extensions = Object::with_capacity(2);
+    extensions.add_field("code", Value::scalar(code.to_string()));
+    if let Some(field) = field {
+        extensions.add_field("field", Value::scalar(field.to_string()));
+    }
+    Value::Object(extensions)
+}
@@ -0,0 +1,296 @@
+// src/grpc.rs
+
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::future::Shared;
+use futures::{Stream, StreamExt};
+use sqlx::PgPool;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_rustls::server::TlsStream;
+use tonic::transport::server::Connected;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+
+use crate::config::Config;
+use crate::db;
+use crate::models::{InsertablePerson, Person};
+use crate::rate_limit::{self, Access, RateLimiter};
+use crate::resource::{Invalid, Resource};
+use crate::server;
+
+pub mod proto {
+    tonic::include_proto!("persons");
+}
+
+use proto::person_service_server::{PersonService, PersonServiceServer};
+
+/// Persons `List` sends ahead of a slow client
+const LIST_BUFFER: usize = 16;
+
+///
+/// The `PersonService` of `proto/persons.proto`, over the `db` functions
+///
+#[derive(Clone)]
+pub struct PersonsService {
+    pool: PgPool,
+    limiter: Option<RateLimiter>,
+}
+
+impl PersonsService {
+    pub fn new(pool: PgPool) -> PersonsService {
+        PersonsService {
+            pool,
+            limiter: None,
+        }
+    }
+
+    ///
+    /// Refuses the calls over the quotas of `limiter` with `RESOURCE_EXHAUSTED`,
+    /// `Get` and `List` counting as reads, the other calls as writes
+    ///
+    pub fn with_limiter(mut self, limiter: RateLimiter) -> Self {
+        self.limiter = Some(limiter);
+        self
+    }
+
+    ///
+    /// Takes a token from the bucket of the client,
+    /// known by its metadata or its address as the HTTP clients are
+    ///
+    fn limit<T>(&self, request: &Request<T>, access: Access) -> Result<(), Status> {
+        let limiter = match &self.limiter {
+            Some(limiter) => limiter,
+            None => return Ok(()),
+        };
+        let metadata = |name| {
+            request
+                .metadata()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+        };
+        let key = rate_limit::key_of(
+            limiter.key_strategy(),
+            metadata("authorization"),
+            metadata("x-api-token"),
+            metadata("x-forwarded-user"),
+            request.remote_addr().map(|addr| addr.ip()),
+        );
+        limiter.check(access, key).map_err(|limited| {
+            tracing::warn!("GRPC : {:?} quota exceeded", access);
+            Status::resource_exhausted(format!(
+                "too many requests, retry in {} s",
+                limited.retry_after_secs()
+            ))
+        })
+    }
+}
+
+impl From<Person> for proto::Person {
+    fn from(person: Person) -> proto::Person {
+        proto::Person {
+            id: person.id,
+            first_name: person.first_name,
+            last_name: person.last_name,
+            updated_at: person.updated_at.unwrap_or(0),
+        }
+    }
+}
+
+impl From<proto::PersonInput> for InsertablePerson {
+    fn from(input: proto::PersonInput) -> InsertablePerson {
+        InsertablePerson {
+            first_name: input.first_name,
+            last_name: input.last_name,
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl PersonService for PersonsService {
+    async fn get(
+        &self,
+        request: Request<proto::GetRequest>,
+    ) -> Result<Response<proto::Person>, Status> {
+        self.limit(&request, Access::Read)?;
+        let id = request.into_inner().id;
+        let person = db::find::<Person>(id, &self.pool)
+            .await
+            .map_err(|err| status(err, id))?;
+        Ok(Response::new(person.into()))
+    }
+
+    type ListStream = Pin<Box<dyn Stream<Item = Result<proto::Person, Status>> + Send + Sync>>;
+
+    async fn list(
+        &self,
+        request: Request<proto::ListRequest>,
+    ) -> Result<Response<Self::ListStream>, Status> {
+        self.limit(&request, Access::Read)?;
+        // the persons are read while they are sent, never all in memory
+        let (tx, rx) = mpsc::channel(LIST_BUFFER);
+        let pool = self.pool.clone();
+        tokio::spawn(async move { db::stream_list::<Person>(&pool, tx).await });
+        let persons = rx.map(|person: anyhow::Result<Person>| {
+            person
+                .map(proto::Person::from)
+                .map_err(|err| status(err, 0))
+        });
+        Ok(Response::new(Box::pin(persons)))
+    }
+
+    async fn create(
+        &self,
+        request: Request<proto::CreateRequest>,
+    ) -> Result<Response<proto::Person>, Status> {
+        self.limit(&request, Access::Write)?;
+        let person = input(request.into_inner().person)?;
+        let person = db::add::<Person>(&self.pool, person)
+            .await
+            .map_err(|err| status(err, 0))?;
+        tracing::info!("GRPC : person {} created", person.id);
+        Ok(Response::new(person.into()))
+    }
+
+    async fn update(
+        &self,
+        request: Request<proto::UpdateRequest>,
+    ) -> Result<Response<proto::Person>, Status> {
+        self.limit(&request, Access::Write)?;
+        let proto::UpdateRequest { id, person } = request.into_inner();
+        let person = input(person)?;
+        let person = db::update::<Person>(id, person, &self.pool)
+            .await
+            .map_err(|err| status(err, id))?;
+        Ok(Response::new(person.into()))
+    }
+
+    async fn delete(
+        &self,
+        request: Request<proto::DeleteRequest>,
+    ) -> Result<Response<proto::DeleteReply>, Status> {
+        self.limit(&request, Access::Write)?;
+        let id = request.into_inner().id;
+        let deleted = db::delete::<Person>(id, &self.pool)
+            .await
+            .map_err(|err| status(err, id))?;
+        if deleted == 0 {
+            return Err(not_found(id));
+        }
+        Ok(Response::new(proto::DeleteReply {}))
+    }
+}
+
+///
+/// Serves the `PersonService` on `addr` in its own task,
+/// until `signal` completes and the calls in flight are done;
+/// over TLS with the certificate of the HTTPS server when `config.tls` is set,
+/// and within the rate limits of `config.rate_limit`
+///
+pub fn spawn<S>(
+    pool: PgPool,
+    addr: SocketAddr,
+    config: &Config,
+    signal: Shared<S>,
+) -> anyhow::Result<JoinHandle<()>>
+where
+    S: Future<Output = ()> + Send + 'static,
+{
+    let limiter = RateLimiter::new(config.rate_limit.clone());
+    let service = PersonServiceServer::new(PersonsService::new(pool).with_limiter(limiter));
+    let router = Server::builder().add_service(service);
+
+    let served: Pin<Box<dyn Future<Output = Result<(), tonic::transport::Error>> + Send>> =
+        match &config.tls {
+            Some(tls) => {
+                let (addr, incoming) = server::tls_incoming(addr, tls, signal.clone())?;
+                tracing::info!("GRPC : listening on {} over TLS", addr);
+                let incoming = incoming.map(|conn| conn.map(TlsConn));
+                Box::pin(router.serve_with_incoming_shutdown(incoming, signal))
+            }
+            None => {
+                tracing::info!("GRPC : listening on {}", addr);
+                Box::pin(router.serve_with_shutdown(addr, signal))
+            }
+        };
+    Ok(tokio::spawn(async move {
+        if let Err(err) = served.await {
+            tracing::error!("GRPC : {}", err);
+        }
+    }))
+}
+
+///
+/// A TLS connection of the HTTPS acceptor, given to tonic with its client address
+///
+struct TlsConn(TlsStream<TcpStream>);
+
+impl Connected for TlsConn {
+    fn remote_addr(&self) -> Option<SocketAddr> {
+        self.0.get_ref().0.peer_addr().ok()
+    }
+}
+
+impl AsyncRead for TlsConn {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for TlsConn {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
+
+///
+/// The person of a request, checked as the forms are
+///
+fn input(person: Option<proto::PersonInput>) -> Result<InsertablePerson, Status> {
+    let person = InsertablePerson::from(person.unwrap_or_default());
+    Person::validate(&person).map_err(invalid)?;
+    Ok(person)
+}
+
+fn invalid(invalid: Invalid) -> Status {
+    Status::invalid_argument(invalid.to_string())
+}
+
+fn not_found(id: i32) -> Status {
+    Status::not_found(format!("no {} with id {}", Person::NAME, id))
+}
+
+///
+/// The status of a failed database call, the details are only logged
+///
+fn status(err: anyhow::Error, id: i32) -> Status {
+    match err.downcast_ref::<sqlx::Error>() {
+        Some(sqlx::Error::RowNotFound) => not_found(id),
+        _ => {
+            tracing::error!("GRPC : {}", err);
+            Status::internal("could not execute the request")
+        }
+    }
+}
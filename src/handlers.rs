@@ -1,144 +1,582 @@
 //src/handlers.rs
 
+use std::collections::BTreeMap;
 use std::convert::Infallible;
 use std::error::Error;
+use std::future::Future;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
+use schemars::JsonSchema;
 use serde::Serialize;
 
 use sqlx::PgPool;
 
+use warp::http::header::{HeaderValue, WARNING};
 use warp::http::StatusCode;
+use futures::StreamExt;
+use warp::sse::ServerSentEvent;
 use warp::{reject, Rejection, Reply};
 
+use juniper::http::graphiql::graphiql_source;
 use tera::{Context};
 
+use crate::assets;
+use crate::changes::{ChangeFeed, Event};
+use crate::conditional::{Preconditions, Validators};
 use crate::db;
 use crate::errors::CustError;
-use crate::models::{InsertablePerson,};
+use crate::jobs::{self, ExportPersons};
+use crate::metrics;
+use crate::migrations;
+use crate::openapi;
+use crate::rate_limit::RateLimited;
+use crate::repository::Repository;
+use crate::resource::{DuplicatesQuery, Invalid, ListVersion, MergeRequest, Resource, DUPLICATE_THRESHOLD};
+use crate::request_id;
+use crate::models::{ComponentHealth, HealthReport, HealthStatus};
 
 use crate::template_setup::tera::render;
+use crate::webhooks::{self, NewWebhook};
 use warp::reject::Reject;
 use std::fmt::Display;
 
 use thiserror::Error;
 
-pub async fn page_home_hdler() -> Result<Box<dyn Reply>, Rejection> {
-    tracing::info!("HDLR : chargement page home");
-    let ctx = Context::new();
-    let body = render("index.html", &ctx).unwrap();
-    Ok(Box::new(warp::reply::html(body)))
+///
+/// Renders a template into an html reply
+/// a render error is logged and rejected as a 500 ServerError
+///
+fn render_html(name: &str, ctx: &Context) -> Result<Box<dyn Reply>, Rejection> {
+    Ok(Box::new(warp::reply::html(render_body(name, ctx)?)))
 }
 
-pub async fn page_add_hdler() -> Result<Box<dyn Reply>, Rejection> {
-    tracing::info!("HDLR : chargement page add");
-    let ctx = Context::new();
-    let body = render("add_person.html", &ctx).unwrap();
-    Ok(Box::new(warp::reply::html(body)))
+fn render_body(name: &str, ctx: &Context) -> Result<String, Rejection> {
+    render(name, ctx).map_err(|err| {
+        tracing::error!("HDLR : error rendering {} : {:?}", name, err);
+        reject::custom(ServerError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "TEMPLATE_ERROR",
+        ))
+    })
 }
 
-pub async fn find_person_by_id_hdler(id: i32, pool: PgPool,) -> Result<Box<dyn Reply>, Rejection> {
-    let res = db::find_person_by_id(id, &pool).await;
-    match res {
-        Ok(person) => {
-            tracing::info!("HDLR : Personne trouvée : {}, {}", &person.last_name, &person.first_name);
+pub async fn page_home_hdler(ctx: Context) -> Result<Box<dyn Reply>, Rejection> {
+    tracing::info!("HDLR : chargement page home");
+    render_html("index.html", &ctx)
+}
 
-            let mut ctx = Context::new();
-            ctx.insert("person", &person);
+pub async fn page_add_hdler<T: Resource>(ctx: Context) -> Result<Box<dyn Reply>, Rejection> {
+    tracing::info!("HDLR : chargement page add {}", T::NAME);
+    render_html(T::ADD_TEMPLATE, &ctx)
+}
 
-            let body = render("modify_person.html", &ctx).unwrap();
-            tracing::info!("chargement page modify");
-            Ok(Box::new(warp::reply::html(body)))
+///
+/// Shows the modify page of one row
+/// with a strong ETag, answered with a 304 when the client has the page already
+///
+pub async fn find_hdler<T: Resource, R: Repository<T>>(
+    id: i32,
+    preconditions: Preconditions,
+    mut ctx: Context,
+    repo: R,
+) -> Result<Box<dyn Reply>, Rejection> {
+    match repo.find(id).await {
+        Ok(item) => {
+            tracing::info!("HDLR : {} {} trouvé", T::NAME, id);
+
+            ctx.insert(T::NAME, &item);
+            let body = render_body(T::EDIT_TEMPLATE, &ctx)?;
+            let validators = Validators::strong(body.as_bytes(), item.updated_at());
+            if preconditions.is_fresh(&validators) {
+                return Ok(Box::new(validators.not_modified()));
+            }
+            Ok(Box::new(validators.apply(warp::reply::html(body).into_response())))
         },
         Err(_) => {
-            tracing::info!("HDLR : Erreur: personne pas trouvée !");
+            tracing::info!("HDLR : Erreur: {} {} pas trouvé !", T::NAME, id);
             Err(reject::not_found())
         },
     }
 }
 
 ///
-/// Handles the request to show a list of persons in the DB
-/// Shows the list in the Tera template
+/// Handles the request to show the list of the rows in the DB
+/// with a weak ETag from the version of the rows shown
 ///
-pub async fn list_persons_hdler(pool: PgPool,) -> Result<Box<dyn Reply>, Rejection> {
-    let res = db::list_persons(&pool).await;
-    match res {
-        Ok(list_persons) => {
-            tracing::info!("HDLR : Liste des personnes trouvée");
+pub async fn list_hdler<T: Resource, R: Repository<T>>(
+    preconditions: Preconditions,
+    ctx: Context,
+    repo: R,
+) -> Result<Box<dyn Reply>, Rejection> {
+    // the live version only answers 304: an ETag given out always comes from a page,
+    // so it matches the live version only while that page is still current
+    match repo.version().await {
+        Ok(Some(version)) => {
+            let validators = Validators::weak(version.count, version.updated_at);
+            if preconditions.is_fresh(&validators) {
+                tracing::info!("HDLR : Liste des {} inchangée", T::PLURAL);
+                return Ok(Box::new(validators.not_modified()));
+            }
+        }
+        Ok(None) => {}
+        Err(err) => tracing::info!("HDLR : no version of the {} : {}", T::PLURAL, err),
+    }
+    let items = list_items::<T, R>(repo).await?;
+    // the ETag is that of the rows rendered, which may come from the cache
+    let validators = ListVersion::of(&items).map(|version| Validators::weak(version.count, version.updated_at));
+    let page = render_items::<T>(&items, ctx, None)?;
+    match validators {
+        Some(validators) => Ok(Box::new(validators.apply(page.into_response()))),
+        None => Ok(page),
+    }
+}
 
-            let mut ctx = Context::new();
-            ctx.insert("persons", &list_persons);
-            let body = render("persons.html", &ctx).unwrap();
+///
+/// Shows the list in the Tera template
+/// the warning, if any, is shown above the list
+///
+async fn render_list<T: Resource, R: Repository<T>>(repo: R, ctx: Context, warning: Option<&str>) -> Result<Box<dyn Reply>, Rejection> {
+    let items = list_items::<T, R>(repo).await?;
+    render_items::<T>(&items, ctx, warning)
+}
 
-            Ok(Box::new(warp::reply::html(body)))
+///
+/// Reads the list from the repository
+///
+async fn list_items<T: Resource, R: Repository<T>>(repo: R) -> Result<Vec<T>, Rejection> {
+    match repo.list().await {
+        Ok(items) => {
+            tracing::info!("HDLR : Liste des {} trouvée", T::PLURAL);
+            Ok(items)
         },
         Err(_) => {
-            tracing::info!("HDLR : Erreur: liste personne pas trouvée !");
+            tracing::info!("HDLR : Erreur: liste des {} pas trouvée !", T::PLURAL);
             Err(reject::not_found())
         },
     }
 }
 
-
+///
+/// Renders the rows in the list template
+///
+fn render_items<T: Resource>(items: &[T], mut ctx: Context, warning: Option<&str>) -> Result<Box<dyn Reply>, Rejection> {
+    ctx.insert(T::PLURAL, &items);
+    ctx.insert("warning", &warning);
+    render_html(T::LIST_TEMPLATE, &ctx)
+}
 
 ///
-/// Handles request to add a person to the DB
-/// redirects to the list persons page
+/// Handles request to add a row to the DB
+/// shows the list page, with a warning when the row looks like one already there
 ///
-pub async fn add_person_hdler(insert_pers: InsertablePerson, pool: PgPool,) -> Result<Box<dyn Reply>, Rejection> {
-    let res = db::add_person(&pool, insert_pers).await;
-    match res {
-        Ok(pers) => {
-            tracing::info!("HDLR : created person : {:?}", &pers);
-            Ok(list_persons_hdler(pool.clone()).await.unwrap())
+pub async fn add_hdler<T: Resource, R: Repository<T>>(item: T::Insertable, ctx: Context, repo: R) -> Result<Box<dyn Reply>, Rejection> {
+    validate::<T>(&item)?;
+    // looked up first, the new row would be its own best match
+    let similar = repo.similar(&item).await.unwrap_or_else(|err| {
+        tracing::warn!("HDLR : could not look for duplicates : {}", err);
+        Vec::new()
+    });
+    match repo.add(item).await {
+        Ok(item) => {
+            tracing::info!("HDLR : created {} : {}", T::NAME, item.id());
+            if similar.is_empty() {
+                return render_list::<T, R>(repo, ctx, None).await;
+            }
+
+            let ids: Vec<String> = similar.iter().map(|(other, _)| other.id().to_string()).collect();
+            let warning = format!(
+                "{} {} looks like a duplicate of {} {}",
+                T::NAME,
+                item.id(),
+                T::NAME,
+                ids.join(", ")
+            );
+            tracing::info!("HDLR : {}", warning);
+            let page = render_list::<T, R>(repo, ctx, Some(&warning)).await?;
+            let mut response = page.into_response();
+            if let Ok(value) = HeaderValue::from_str(&format!("199 - \"{}\"", warning)) {
+                response.headers_mut().insert(WARNING, value);
+            }
+            Ok(Box::new(response))
         }
-        Err(err) => {
-            let error = ErrorMessage { code: 405, message: "HDLR : erreur création personne".to_string() };
+        Err(_) => {
+            let error = ErrorMessage::new(405, "HDLR : erreur création");
             Ok(Box::new(warp::reply::json(&error)))
         }
     }
 }
 
-pub async fn delete_person_hdler(pers_id: i32, pool: PgPool) -> Result<Box<dyn Reply>, Rejection> {
-    let res = db::delete_person(pers_id, &pool).await;
-    match res {
-        Ok(id) => {
-            tracing::info!("HDLR : id person deleted : {:?}", &id);
-            Ok(list_persons_hdler(pool.clone()).await.unwrap())
+pub async fn delete_hdler<T: Resource, R: Repository<T>>(id: i32, ctx: Context, repo: R) -> Result<Box<dyn Reply>, Rejection> {
+    match repo.delete(id).await {
+        Ok(deleted) => {
+            tracing::info!("HDLR : {} {} deleted : {}", T::NAME, id, deleted);
+            render_list::<T, R>(repo, ctx, None).await
         }
         Err(_) => {
-            tracing::info!("HDLR : error deleting person");
+            tracing::info!("HDLR : error deleting {}", T::NAME);
             Ok(Box::new(StatusCode::BAD_REQUEST))
         }
     }
 }
 
-pub async fn update_person_hdler(
-    pers_id: i32,
-    modifyed_pers: InsertablePerson,
-    pool: PgPool,
+pub async fn update_hdler<T: Resource, R: Repository<T>>(
+    id: i32,
+    item: T::Insertable,
+    ctx: Context,
+    repo: R,
 ) -> Result<Box<dyn Reply>, Rejection> {
-
-    tracing::info!("HDLR : Person send to handler update: {:?}", &modifyed_pers);
-
-    let res = db::update_person(pers_id, modifyed_pers, &pool).await;
-    match res {
-        Ok(pers) => {
-            tracing::info!(" HDLR : Person updated : {:?}", &pers);
-            Ok(list_persons_hdler(pool.clone()).await.unwrap())
+    validate::<T>(&item)?;
+    match repo.update(id, item).await {
+        Ok(item) => {
+            tracing::info!("HDLR : {} updated : {}", T::NAME, item.id());
+            render_list::<T, R>(repo, ctx, None).await
         }
         Err(_) => {
-            tracing::info!("HDLR : error updating person");
+            tracing::info!("HDLR : error updating {}", T::NAME);
             Ok(Box::new(StatusCode::BAD_REQUEST))
         }
     }
 }
 
+///
+/// Reports the pairs of rows likely to be duplicates, the most similar first
+///
+pub async fn duplicates_hdler<T: Resource, R: Repository<T>>(
+    query: DuplicatesQuery,
+    repo: R,
+) -> Result<Box<dyn Reply>, Rejection> {
+    let threshold = query.threshold.unwrap_or(DUPLICATE_THRESHOLD);
+    if !(0.0..=1.0).contains(&threshold) {
+        return Err(reject::custom(Invalid::new("threshold", "must be between 0 and 1")));
+    }
+    match repo.duplicates(threshold).await {
+        Ok(duplicates) => Ok(Box::new(warp::reply::json(&duplicates))),
+        Err(err) => Err(db_error("looking for duplicates", err)),
+    }
+}
+
+///
+/// Merges the row `from` into the row `id`, which is kept and returned
+///
+pub async fn merge_hdler<T: Resource, R: Repository<T>>(
+    id: i32,
+    merge: MergeRequest,
+    repo: R,
+) -> Result<Box<dyn Reply>, Rejection> {
+    if merge.from == id {
+        return Err(reject::custom(Invalid::new("from", "must not be the kept id")));
+    }
+    for existing in &[id, merge.from] {
+        if repo.find(*existing).await.is_err() {
+            tracing::info!("HDLR : Erreur: {} {} pas trouvé !", T::NAME, existing);
+            return Err(reject::not_found());
+        }
+    }
+    match repo.merge(id, merge.from).await {
+        Ok(kept) => {
+            tracing::info!("HDLR : {} {} merged into {}", T::NAME, merge.from, id);
+            Ok(Box::new(warp::reply::json(&kept)))
+        }
+        Err(err) => Err(db_error("merging", err)),
+    }
+}
+
+///
+/// Sends a request for a merged row to the row it was merged into
+/// the ids never merged stay not found
+///
+pub async fn merged_hdler<T: Resource, R: Repository<T>>(id: i32, base_path: String, repo: R) -> Result<Box<dyn Reply>, Rejection> {
+    match repo.merged_into(id).await {
+        Ok(Some(into)) => {
+            let location = format!(
+                "{}{}",
+                base_path,
+                T::ITEM_ROUTE.replace("{id}", &into.to_string())
+            );
+            Ok(Box::new(warp::reply::with_header(
+                StatusCode::MOVED_PERMANENTLY,
+                "location",
+                location,
+            )))
+        }
+        Ok(None) => Err(reject::not_found()),
+        Err(err) => Err(db_error("looking for a merge", err)),
+    }
+}
+
+///
+/// Rejects an invalid form before it reaches the repository
+///
+fn validate<T: Resource>(item: &T::Insertable) -> Result<(), Rejection> {
+    T::validate(item).map_err(|invalid| {
+        tracing::info!("HDLR : invalid {} : {}", T::NAME, invalid);
+        reject::custom(invalid)
+    })
+}
+
+///
+/// Streams the changes of the persons as Server-Sent Events
+/// a client reconnecting with `Last-Event-ID` first gets the changes it missed
+///
+pub async fn person_events_hdler(last_event_id: Option<String>, feed: ChangeFeed) -> Result<Box<dyn Reply>, Rejection> {
+    tracing::info!("HDLR : events subscriber, last event {:?}", &last_event_id);
+    let events = feed
+        .subscribe(last_event_id.as_deref())
+        .map(|event| Ok::<_, Infallible>(sse_event(event)));
+    Ok(Box::new(warp::sse::reply(warp::sse::keep_alive().stream(events))))
+}
+
+fn sse_event(event: Event) -> impl ServerSentEvent {
+    let (id, name, data) = match event {
+        Event::Change { id, change } => (id, "change", serde_json::to_string(&change).unwrap_or_default()),
+        Event::Reset { id } => (id, "reset", "{}".to_string()),
+    };
+    (warp::sse::id(id.to_string()), warp::sse::event(name), warp::sse::data(data))
+}
+
+///
+/// Lists the webhook subscriptions
+///
+pub async fn list_webhooks_hdler(pool: PgPool) -> Result<Box<dyn Reply>, Rejection> {
+    match webhooks::list(&pool).await {
+        Ok(list) => Ok(Box::new(warp::reply::json(&list))),
+        Err(err) => Err(db_error("listing the webhooks", err)),
+    }
+}
+
+///
+/// Creates a webhook subscription
+/// the answer is the only one showing its secret
+///
+pub async fn create_webhook_hdler(new: NewWebhook, allow_private: bool, pool: PgPool) -> Result<Box<dyn Reply>, Rejection> {
+    new.validate(allow_private).map_err(|invalid| {
+        tracing::info!("HDLR : invalid webhook : {}", invalid);
+        reject::custom(invalid)
+    })?;
+    match webhooks::create(&pool, new).await {
+        Ok(created) => {
+            tracing::info!("HDLR : webhook {} created for {}", created.webhook.id, created.webhook.url);
+            let json = warp::reply::json(&created);
+            Ok(Box::new(warp::reply::with_status(json, StatusCode::CREATED)))
+        }
+        Err(err) => Err(db_error("creating a webhook", err)),
+    }
+}
+
+pub async fn delete_webhook_hdler(id: i32, pool: PgPool) -> Result<Box<dyn Reply>, Rejection> {
+    match webhooks::delete(id, &pool).await {
+        Ok(0) => Err(reject::not_found()),
+        Ok(_) => {
+            tracing::info!("HDLR : webhook {} deleted", id);
+            Ok(Box::new(StatusCode::NO_CONTENT))
+        }
+        Err(err) => Err(db_error("deleting a webhook", err)),
+    }
+}
+
+///
+/// Shows the latest deliveries of a webhook and their attempts
+///
+pub async fn webhook_deliveries_hdler(id: i32, pool: PgPool) -> Result<Box<dyn Reply>, Rejection> {
+    match webhooks::deliveries(id, &pool).await {
+        Ok(deliveries) => Ok(Box::new(warp::reply::json(&deliveries))),
+        Err(err) => Err(db_error("reading the deliveries", err)),
+    }
+}
+
+///
+/// The admin page of the background jobs
+/// the number of jobs in each status and the latest jobs
+///
+pub async fn admin_jobs_hdler(mut ctx: Context, pool: PgPool) -> Result<Box<dyn Reply>, Rejection> {
+    tracing::info!("HDLR : chargement page admin jobs");
+    let counts = jobs::counts(&pool)
+        .await
+        .map_err(|err| db_error("counting the jobs", err))?;
+    let recent = jobs::recent(&pool, 100)
+        .await
+        .map_err(|err| db_error("listing the jobs", err))?;
+    ctx.insert("counts", &counts);
+    ctx.insert("jobs", &recent);
+    render_html("admin_jobs.html", &ctx)
+}
+
+///
+/// Queues an export of the persons to a CSV file of `export_dir`
+/// and goes back to the admin page
+///
+pub async fn export_persons_hdler(export_dir: PathBuf, base_path: String, pool: PgPool) -> Result<Box<dyn Reply>, Rejection> {
+    let job = ExportPersons::timestamped(&export_dir);
+    jobs::enqueue(&pool, &job)
+        .await
+        .map_err(|err| db_error("queuing the export", err))?;
+    let location = format!("{}/admin/jobs", base_path);
+    Ok(Box::new(warp::reply::with_header(
+        StatusCode::SEE_OTHER,
+        "location",
+        location,
+    )))
+}
+
+///
+/// Logs a database error and rejects it as a 500 ServerError
+///
+fn db_error(doing: &str, err: anyhow::Error) -> Rejection {
+    tracing::error!("HDLR : error {} : {}", doing, err);
+    reject::custom(ServerError::new(StatusCode::INTERNAL_SERVER_ERROR, "DATABASE_ERROR"))
+}
+
+///
+/// Liveness probe
+/// answers as long as the process serves requests
+///
+pub async fn healthz_hdler() -> Result<Box<dyn Reply>, Rejection> {
+    let report = HealthReport::new(BTreeMap::new());
+    Ok(Box::new(warp::reply::json(&report)))
+}
+
+///
+/// Readiness probe
+/// checks the database and the migrations, 503 if one of them is down
+///
+pub async fn readyz_hdler(pool: PgPool, timeout: Duration) -> Result<Box<dyn Reply>, Rejection> {
+    let report = readiness(&pool, timeout).await;
+    let code = match report.status {
+        HealthStatus::Up => StatusCode::OK,
+        HealthStatus::Down => {
+            tracing::warn!("HDLR : not ready : {:?}", &report);
+            StatusCode::SERVICE_UNAVAILABLE
+        }
+    };
+    Ok(Box::new(warp::reply::with_status(warp::reply::json(&report), code)))
+}
+
+///
+/// Serves a static asset
+///
+pub async fn static_asset_hdler(
+    tail: warp::path::Tail,
+    if_none_match: Option<String>,
+) -> Result<Box<dyn Reply>, Rejection> {
+    match assets::reply(tail.as_str(), if_none_match) {
+        Some(response) => Ok(Box::new(response)),
+        None => Err(reject::not_found()),
+    }
+}
+
+///
+/// Serves the OpenAPI document
+///
+pub async fn openapi_hdler(base_path: String) -> Result<Box<dyn Reply>, Rejection> {
+    Ok(Box::new(warp::reply::json(&openapi::spec(&base_path))))
+}
+
+///
+/// Shows the API explorer
+///
+pub async fn api_docs_hdler(mut ctx: Context) -> Result<Box<dyn Reply>, Rejection> {
+    ctx.insert("swagger_ui", &assets::exists("swagger-ui/swagger-ui-bundle.js"));
+    render_html("api_docs.html", &ctx)
+}
+
+///
+/// The GraphiQL explorer, sending its queries to the `/graphql` of the app
+///
+pub async fn graphiql_hdler(base_path: String) -> Result<Box<dyn Reply>, Rejection> {
+    let endpoint = format!("{}/graphql", base_path);
+    Ok(Box::new(warp::reply::html(graphiql_source(&endpoint, None))))
+}
+
+///
+/// Serves the Prometheus metrics
+///
+pub async fn metrics_hdler(pool: PgPool) -> Result<Box<dyn Reply>, Rejection> {
+    match metrics::gather(&pool) {
+        Ok((content_type, body)) => Ok(Box::new(warp::reply::with_header(
+            body,
+            "content-type",
+            content_type,
+        ))),
+        Err(err) => {
+            tracing::error!("HDLR : error encoding metrics : {:?}", err);
+            Err(reject::custom(ServerError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "METRICS_ERROR",
+            )))
+        }
+    }
+}
+
+///
+/// The state of the database and of the migrations,
+/// each check given at most `timeout`
+///
+pub async fn readiness(pool: &PgPool, timeout: Duration) -> HealthReport {
+    let mut components = BTreeMap::new();
+    components.insert("database", check(timeout, db::ping(pool)).await);
+    components.insert(
+        "migrations",
+        check(timeout, async {
+            let pending = migrations::pending(pool).await?;
+            if pending.is_empty() {
+                Ok(())
+            } else {
+                Err(anyhow::anyhow!("pending migrations : {}", pending.join(", ")))
+            }
+        })
+        .await,
+    );
+    HealthReport::new(components)
+}
+
+///
+/// Runs a health check within the timeout and measures its latency
+///
+async fn check<F>(timeout: Duration, fut: F) -> ComponentHealth
+where
+    F: Future<Output = anyhow::Result<()>>,
+{
+    let start = Instant::now();
+    match tokio::time::timeout(timeout, fut).await {
+        Ok(Ok(())) => ComponentHealth::up(start.elapsed()),
+        Ok(Err(err)) => ComponentHealth::down(start.elapsed(), err.to_string()),
+        Err(_) => ComponentHealth::down(start.elapsed(), "timeout".to_string()),
+    }
+}
+
 /// An API error serializable to JSON.
-#[derive(Serialize)]
-struct ErrorMessage {
+/// Carries the request id so a client report can be matched with the logs.
+#[derive(Serialize, JsonSchema)]
+pub struct ErrorMessage {
     code: u16,
     message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+}
+
+impl ErrorMessage {
+    fn new(code: u16, message: &str) -> ErrorMessage {
+        ErrorMessage {
+            code,
+            message: message.to_string(),
+            request_id: request_id::current().map(|id| id.to_string()),
+        }
+    }
+}
+
+///
+/// Same as `handle_rejection`, but lets the "not found" rejections through
+/// so an enclosing application can still try its own routes
+///
+pub async fn handle_rejection_except_not_found(err: Rejection) -> Result<Box<dyn Reply>, Rejection> {
+    if err.is_not_found() {
+        return Err(err);
+    }
+    match handle_rejection(err).await {
+        Ok(reply) => Ok(reply),
+        Err(never) => match never {},
+    }
 }
 
 // This function receives a `Rejection` and tries to return a custom
@@ -147,6 +585,12 @@ pub async fn handle_rejection(err: Rejection) -> Result<Box<dyn Reply>, Infallib
     let code;
     let message;
 
+    if let Some(limited) = err.find::<RateLimited>() {
+        let code = StatusCode::TOO_MANY_REQUESTS;
+        let json = warp::reply::json(&ErrorMessage::new(code.as_u16(), "TOO_MANY_REQUESTS"));
+        return Ok(Box::new(limited.with_headers(warp::reply::with_status(json, code))));
+    }
+
     if err.is_not_found() {
         code = StatusCode::NOT_FOUND;
         message = "NOT_FOUND";
@@ -164,9 +608,23 @@ pub async fn handle_rejection(err: Rejection) -> Result<Box<dyn Reply>, Infallib
             None => "BAD_REQUEST",
         };
         code = StatusCode::BAD_REQUEST;
+    } else if let Some(invalid) = err.find::<Invalid>() {
+        code = StatusCode::BAD_REQUEST;
+        let json = warp::reply::json(&ErrorMessage::new(
+            code.as_u16(),
+            &format!("INVALID: {}", invalid),
+        ));
+        return Ok(Box::new(warp::reply::with_status(json, code)));
+    } else if let Some(e) = err.find::<warp::cors::CorsForbidden>() {
+        tracing::info!("HDLR : CORS request refused : {}", e);
+        code = StatusCode::FORBIDDEN;
+        message = "CORS_FORBIDDEN";
     } else if let Some(_) = err.find::<warp::reject::MethodNotAllowed>() {
         code = StatusCode::METHOD_NOT_ALLOWED;
         message = "METHOD_NOT_ALLOWED";
+    } else if let Some(e) = err.find::<ServerError>() {
+        code = e.status;
+        message = e.msg.as_str();
     } else if let Some(e) = err.find::<CustError>() {
         match e {
             CustError::DBQueryError(_) => {
@@ -189,10 +647,7 @@ pub async fn handle_rejection(err: Rejection) -> Result<Box<dyn Reply>, Infallib
         message = "UNHANDLED_REJECTION";
     }
 
-    let json = warp::reply::json(&ErrorMessage {
-        code: code.as_u16(),
-        message: message.into(),
-    });
+    let json = warp::reply::json(&ErrorMessage::new(code.as_u16(), message));
 
     Ok(Box::new(warp::reply::with_status(json, code)))
 }
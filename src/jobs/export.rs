@@ -0,0 +1,74 @@
+// src/jobs/export.rs
+
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::db;
+use crate::models::Person;
+
+use super::{Job, JobContext};
+
+///
+/// Writes the persons to a CSV file
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExportPersons {
+    pub path: PathBuf,
+}
+
+impl ExportPersons {
+    ///
+    /// An export to `persons-<seconds since the epoch>.csv` in `dir`
+    ///
+    pub fn timestamped(dir: &Path) -> ExportPersons {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        ExportPersons {
+            path: dir.join(format!("persons-{}.csv", timestamp)),
+        }
+    }
+}
+
+#[async_trait]
+impl Job for ExportPersons {
+    const KIND: &'static str = "export_persons";
+
+    async fn run(self, ctx: JobContext) -> anyhow::Result<()> {
+        let persons = db::list::<Person>(&ctx.pool).await?;
+        let mut csv = String::from("id,first_name,last_name\n");
+        for person in &persons {
+            csv.push_str(&format!(
+                "{},{},{}\n",
+                person.id,
+                csv_field(&person.first_name),
+                csv_field(&person.last_name)
+            ));
+        }
+
+        if let Some(dir) = self.path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        tokio::fs::write(&self.path, csv).await?;
+        tracing::info!(
+            "JOBS : {} persons exported to {}",
+            persons.len(),
+            self.path.display()
+        );
+        Ok(())
+    }
+}
+
+///
+/// Quotes a field holding a separator, a quote or a line break
+///
+fn csv_field(value: &str) -> String {
+    if value.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
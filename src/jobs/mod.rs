@@ -0,0 +1,163 @@
+// src/jobs/mod.rs
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::future;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sqlx::postgres::PgRow;
+use sqlx::{PgPool, Row};
+
+use crate::db::{self, PgTx};
+
+mod export;
+pub mod worker;
+
+pub use export::ExportPersons;
+
+///
+/// What a job gets to do its work
+///
+#[derive(Clone)]
+pub struct JobContext {
+    pub pool: PgPool,
+}
+
+///
+/// A kind of background job
+/// the job is stored as its JSON in the `jobs` table and deserialized
+/// by the worker claiming it, so it carries everything `run` needs
+///
+#[async_trait]
+pub trait Job: Serialize + DeserializeOwned + Send + Sync + 'static {
+    /// The name of the job in the `kind` column
+    const KIND: &'static str;
+    /// Attempts before the job is declared dead
+    const MAX_ATTEMPTS: i32 = 5;
+
+    async fn run(self, ctx: JobContext) -> anyhow::Result<()>;
+}
+
+pub(crate) type JobFuture = Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send>>;
+
+type Runner = Arc<dyn Fn(&str, JobContext) -> JobFuture + Send + Sync>;
+
+///
+/// The kinds of jobs the workers know how to run
+///
+#[derive(Clone, Default)]
+pub struct Registry {
+    runners: HashMap<&'static str, Runner>,
+}
+
+impl Registry {
+    ///
+    /// The jobs of the application
+    ///
+    pub fn new() -> Registry {
+        Registry::default().register::<ExportPersons>()
+    }
+
+    pub fn register<J: Job>(mut self) -> Registry {
+        let runner: Runner = Arc::new(|payload: &str, ctx: JobContext| -> JobFuture {
+            match serde_json::from_str::<J>(payload) {
+                Ok(job) => job.run(ctx),
+                Err(err) => Box::pin(future::ready(Err(err.into()))),
+            }
+        });
+        self.runners.insert(J::KIND, runner);
+        self
+    }
+
+    fn runner(&self, kind: &str) -> Option<&Runner> {
+        self.runners.get(kind)
+    }
+}
+
+///
+/// A job as shown on the admin page
+///
+#[derive(Serialize, Debug, Clone)]
+pub struct JobRow {
+    pub id: i64,
+    pub kind: String,
+    pub status: String,
+    pub attempts: i32,
+    pub max_attempts: i32,
+    pub run_at: String,
+    pub last_error: Option<String>,
+}
+
+///
+/// Queues a job, to run as soon as a worker is free
+///
+pub async fn enqueue<J: Job>(pool: &PgPool, job: &J) -> anyhow::Result<i64> {
+    let mut tx = db::begin(pool).await?;
+    let id = enqueue_in(&mut tx, job).await?;
+    tx.commit().await?;
+    Ok(id)
+}
+
+///
+/// Queues a job inside the transaction `tx`
+/// the job only exists if the transaction commits
+///
+pub async fn enqueue_in<J: Job>(tx: &mut PgTx, job: &J) -> anyhow::Result<i64> {
+    let id = sqlx::query(
+        "INSERT INTO jobs (kind, payload, max_attempts)
+                VALUES ( $1, $2, $3 )
+                RETURNING id;",
+    )
+    .bind(J::KIND)
+    .bind(serde_json::to_string(job)?)
+    .bind(J::MAX_ATTEMPTS)
+    .map(|row: PgRow| row.get(0))
+    .fetch_one(tx)
+    .await?;
+    tracing::info!("JOBS : {} {} queued", J::KIND, id);
+    Ok(id)
+}
+
+///
+/// The number of jobs in each status
+///
+pub async fn counts(pool: &PgPool) -> anyhow::Result<Vec<(String, i64)>> {
+    let mut tx = db::begin(pool).await?;
+    let counts = sqlx::query("SELECT status, count(*) FROM jobs GROUP BY status ORDER BY status;")
+        .map(|row: PgRow| (row.get(0), row.get(1)))
+        .fetch_all(&mut tx)
+        .await?;
+    tx.commit().await?;
+    Ok(counts)
+}
+
+///
+/// The latest jobs, most recent first
+///
+pub async fn recent(pool: &PgPool, limit: i64) -> anyhow::Result<Vec<JobRow>> {
+    let mut tx = db::begin(pool).await?;
+    let jobs = sqlx::query(
+        "SELECT id, kind, status, attempts, max_attempts, run_at::text, last_error
+                FROM jobs
+                ORDER BY id DESC
+                LIMIT $1;",
+    )
+    .bind(limit)
+    .map(|row: PgRow| JobRow {
+        id: row.get(0),
+        kind: row.get(1),
+        status: row.get(2),
+        attempts: row.get(3),
+        max_attempts: row.get(4),
+        run_at: row.get(5),
+        last_error: row.get(6),
+    })
+    .fetch_all(&mut tx)
+    .await?;
+    tx.commit().await?;
+    Ok(jobs)
+}
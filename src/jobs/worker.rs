@@ -0,0 +1,217 @@
+// src/jobs/worker.rs
+
+use std::time::Duration;
+
+use futures::future::{self, Aborted};
+use sqlx::postgres::PgRow;
+use sqlx::{PgPool, Row};
+use tokio::task::JoinHandle;
+
+use crate::config::JobConfig;
+use crate::db;
+use crate::retry;
+
+use super::{JobContext, JobFuture, Registry};
+
+///
+/// A job claimed by a worker
+///
+struct Claimed {
+    id: i64,
+    kind: String,
+    payload: String,
+    attempts: i32,
+    max_attempts: i32,
+}
+
+///
+/// Starts `config.concurrency` workers
+/// the workers of every instance share the queue, a job is only claimed by one of them
+///
+pub fn spawn(pool: PgPool, registry: Registry, config: JobConfig) -> Vec<JoinHandle<()>> {
+    (0..config.concurrency)
+        .map(|worker| tokio::spawn(run(worker, pool.clone(), registry.clone(), config.clone())))
+        .collect()
+}
+
+async fn run(worker: usize, pool: PgPool, registry: Registry, config: JobConfig) {
+    tracing::info!("JOBS : worker {} started", worker);
+    loop {
+        match run_next(&pool, &registry, &config).await {
+            // there may be more waiting
+            Ok(true) => continue,
+            Ok(false) => {}
+            Err(err) => tracing::warn!("JOBS : worker {} : {}", worker, err),
+        }
+        tokio::time::delay_for(config.poll_interval).await;
+    }
+}
+
+///
+/// Runs the next job due, returns false when there is none
+///
+async fn run_next(pool: &PgPool, registry: &Registry, config: &JobConfig) -> anyhow::Result<bool> {
+    let job = match claim(pool, config).await? {
+        Some(job) => job,
+        None => return Ok(false),
+    };
+
+    let runner = registry.runner(&job.kind);
+    let outcome = match runner {
+        Some(runner) => {
+            let ctx = JobContext { pool: pool.clone() };
+            run_within(runner(&job.payload, ctx), config.timeout).await
+        }
+        None => Err(format!("unknown job kind {}", job.kind)),
+    };
+
+    let (status, error, delay) = settle(
+        outcome,
+        job.attempts,
+        job.max_attempts,
+        runner.is_some(),
+        config,
+    );
+    let reason = error.as_deref().unwrap_or_default();
+    match status {
+        Status::Done => tracing::info!("JOBS : {} {} done", job.kind, job.id),
+        Status::Dead => tracing::warn!("JOBS : {} {} is dead : {}", job.kind, job.id, reason),
+        Status::Queued => tracing::info!(
+            "JOBS : {} {} failed : {}, retrying in {:?}",
+            job.kind,
+            job.id,
+            reason,
+            delay
+        ),
+    }
+    finish(pool, job.id, status.as_str(), error, delay).await?;
+    Ok(true)
+}
+
+///
+/// Runs a job in its own task, so a panicking job only fails itself,
+/// and aborts it when it is still running after `timeout`
+///
+pub(crate) async fn run_within(job: JobFuture, timeout: Duration) -> Result<(), String> {
+    let (job, abort) = future::abortable(job);
+    let task = tokio::spawn(job);
+    match tokio::time::timeout(timeout, task).await {
+        Ok(Ok(Ok(Ok(())))) => Ok(()),
+        Ok(Ok(Ok(Err(err)))) => Err(err.to_string()),
+        Ok(Ok(Err(Aborted))) => Err("aborted".to_string()),
+        Ok(Err(err)) => Err(format!("panicked : {}", err)),
+        Err(_) => {
+            abort.abort();
+            Err(format!("aborted after running for {:?}", timeout))
+        }
+    }
+}
+
+///
+/// The status a job is left in after an attempt
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Status {
+    Done,
+    /// queued again, for a later attempt
+    Queued,
+    /// failed its last attempt, or no worker knows its kind
+    Dead,
+}
+
+impl Status {
+    fn as_str(self) -> &'static str {
+        match self {
+            Status::Done => "done",
+            Status::Queued => "queued",
+            Status::Dead => "dead",
+        }
+    }
+}
+
+///
+/// What becomes of a job after its `attempts`th attempt ended with `outcome`:
+/// its status, its error and the delay before it runs again
+///
+pub(crate) fn settle(
+    outcome: Result<(), String>,
+    attempts: i32,
+    max_attempts: i32,
+    known: bool,
+    config: &JobConfig,
+) -> (Status, Option<String>, Duration) {
+    match outcome {
+        Ok(()) => (Status::Done, None, Duration::from_secs(0)),
+        Err(error) if attempts >= max_attempts || !known => {
+            (Status::Dead, Some(error), Duration::from_secs(0))
+        }
+        Err(error) => (
+            Status::Queued,
+            Some(error),
+            retry::backoff(config.base_delay, config.max_delay, attempts),
+        ),
+    }
+}
+
+///
+/// Takes the next job due, skipping those another worker holds
+/// a running job whose lease expired is taken again, its worker died
+///
+async fn claim(pool: &PgPool, config: &JobConfig) -> anyhow::Result<Option<Claimed>> {
+    let lease = (config.timeout + Duration::from_secs(30)).as_secs_f64();
+    let mut tx = db::begin(pool).await?;
+    let job = sqlx::query(
+        "UPDATE jobs
+                SET status = 'running',
+                    attempts = attempts + 1,
+                    locked_until = now() + make_interval(secs => $1),
+                    updated_at = now()
+                WHERE id = (
+                    SELECT id FROM jobs
+                    WHERE (status = 'queued' AND run_at <= now())
+                       OR (status = 'running' AND locked_until < now())
+                    ORDER BY run_at, id
+                    LIMIT 1
+                    FOR UPDATE SKIP LOCKED)
+                RETURNING id, kind, payload, attempts, max_attempts;",
+    )
+    .bind(lease)
+    .map(|row: PgRow| Claimed {
+        id: row.get(0),
+        kind: row.get(1),
+        payload: row.get(2),
+        attempts: row.get(3),
+        max_attempts: row.get(4),
+    })
+    .fetch_optional(&mut tx)
+    .await?;
+    tx.commit().await?;
+    Ok(job)
+}
+
+async fn finish(
+    pool: &PgPool,
+    id: i64,
+    status: &str,
+    error: Option<String>,
+    delay: Duration,
+) -> anyhow::Result<()> {
+    let mut tx = db::begin(pool).await?;
+    sqlx::query(
+        "UPDATE jobs
+                SET status = $2,
+                    last_error = $3,
+                    run_at = now() + make_interval(secs => $4),
+                    locked_until = NULL,
+                    updated_at = now()
+                WHERE id = $1;",
+    )
+    .bind(id)
+    .bind(status)
+    .bind(error)
+    .bind(delay.as_secs_f64())
+    .execute(&mut tx)
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
@@ -0,0 +1,41 @@
+// src/lib.rs
+
+//! The persons application as a library
+//! `PersonsApp::builder()` gives a warp filter to mount in another application,
+//! the binary only reads the configuration and serves it.
+
+mod app;
+mod assets;
+pub mod cache;
+pub mod changes;
+pub mod cli;
+mod compression;
+mod conditional;
+pub mod config;
+pub mod db;
+mod errors;
+pub mod filters;
+pub mod graphql;
+pub mod grpc;
+pub mod handlers;
+pub mod jobs;
+mod metrics;
+pub mod migrations;
+pub mod models;
+mod openapi;
+pub mod rate_limit;
+pub mod repository;
+pub mod resource;
+mod request_id;
+mod retry;
+pub mod seed;
+//mod routes;
+pub mod server;
+pub mod shutdown;
+mod template_setup;
+#[cfg(test)]
+mod tests;
+mod tls;
+pub mod webhooks;
+
+pub use app::{PersonsApp, PersonsAppBuilder};
@@ -0,0 +1,199 @@
+// src/metrics.rs
+
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use once_cell::sync::Lazy;
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, register_int_gauge, Encoder, HistogramVec,
+    IntCounterVec, IntGauge, TextEncoder,
+};
+use sqlx::PgPool;
+use warp::filters::BoxedFilter;
+use warp::http::{Method, StatusCode};
+use warp::reject::MethodNotAllowed;
+use warp::reply::Response;
+use warp::{Filter, Rejection, Reply};
+
+use crate::handlers;
+
+static HTTP_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "http_requests_total",
+        "Number of HTTP requests by method, route and status",
+        &["method", "route", "status"]
+    )
+    .unwrap()
+});
+
+static HTTP_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "http_request_duration_seconds",
+        "HTTP request latencies by method, route and status",
+        &["method", "route", "status"]
+    )
+    .unwrap()
+});
+
+static DB_QUERY_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "db_query_duration_seconds",
+        "Database query latencies by query and outcome",
+        &["query", "outcome"]
+    )
+    .unwrap()
+});
+
+static DB_POOL_SIZE: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!("db_pool_size", "Number of connections opened by the pool").unwrap()
+});
+
+static DB_POOL_IDLE: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!("db_pool_idle", "Number of idle connections in the pool").unwrap()
+});
+
+static DB_POOL_WAITING: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "db_pool_waiting",
+        "Number of tasks waiting for a connection from the pool"
+    )
+    .unwrap()
+});
+
+static CACHE_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "cache_requests_total",
+        "Lookups in the query cache by table and outcome",
+        &["table", "outcome"]
+    )
+    .unwrap()
+});
+
+static CACHE_ENTRIES: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!("cache_entries", "Number of entries in the query cache").unwrap()
+});
+
+///
+/// The routes instrumented so far, as (method, route template)
+///
+static ROUTES: Lazy<Mutex<BTreeSet<(String, &'static str)>>> =
+    Lazy::new(|| Mutex::new(BTreeSet::new()));
+
+///
+/// Returns the routes built so far, the OpenAPI document is checked against them
+///
+pub fn routes() -> BTreeSet<(String, &'static str)> {
+    ROUTES.lock().unwrap().clone()
+}
+
+///
+/// Wraps a route to count its requests and measure their latency
+/// `route` is the route template (`/persons/{id}`), never the raw path,
+/// so the number of series stays bounded.
+/// Rejections meaning "not this route" are passed along unrecorded,
+/// any other rejection is turned into its error reply here and recorded.
+///
+pub fn instrument<F, R>(method: Method, route: &'static str, filter: F) -> BoxedFilter<(Response,)>
+where
+    F: Filter<Extract = (R,), Error = Rejection> + Clone + Send + Sync + 'static,
+    R: Reply + 'static,
+{
+    ROUTES
+        .lock()
+        .unwrap()
+        .insert((method.as_str().to_string(), route));
+
+    warp::method()
+        .and(warp::any().map(Instant::now))
+        .and(
+            filter
+                .map(Reply::into_response)
+                .or_else(|err: Rejection| async move {
+                    if err.is_not_found() || err.find::<MethodNotAllowed>().is_some() {
+                        return Err(err);
+                    }
+                    let reply = handlers::handle_rejection(err).await.unwrap();
+                    Ok((reply.into_response(),))
+                }),
+        )
+        .map(move |method: Method, start: Instant, response: Response| {
+            record_request(&method, route, response.status(), start.elapsed());
+            response
+        })
+        .boxed()
+}
+
+fn record_request(method: &Method, route: &str, status: StatusCode, elapsed: Duration) {
+    let labels = [method.as_str(), route, status.as_str()];
+    HTTP_REQUESTS.with_label_values(&labels).inc();
+    HTTP_DURATION
+        .with_label_values(&labels)
+        .observe(elapsed.as_secs_f64());
+}
+
+///
+/// Measures a database query, labelled by the name of the `db` function
+///
+pub async fn time_query<F, T>(query: &str, fut: F) -> anyhow::Result<T>
+where
+    F: Future<Output = anyhow::Result<T>>,
+{
+    let start = Instant::now();
+    let res = fut.await;
+    let outcome = if res.is_ok() { "ok" } else { "error" };
+    DB_QUERY_DURATION
+        .with_label_values(&[query, outcome])
+        .observe(start.elapsed().as_secs_f64());
+    res
+}
+
+///
+/// Counts a lookup in the query cache
+///
+pub fn record_cache(table: &str, hit: bool) {
+    let outcome = if hit { "hit" } else { "miss" };
+    CACHE_REQUESTS.with_label_values(&[table, outcome]).inc();
+}
+
+///
+/// Counts the entries added to a query cache, or removed when `delta` is negative
+///
+pub fn record_cache_entries(delta: i64) {
+    CACHE_ENTRIES.add(delta);
+}
+
+///
+/// Counts a task waiting for a pool connection while it lives
+///
+pub struct PoolWaiter;
+
+impl PoolWaiter {
+    pub fn enter() -> PoolWaiter {
+        DB_POOL_WAITING.inc();
+        PoolWaiter
+    }
+}
+
+impl Drop for PoolWaiter {
+    fn drop(&mut self) {
+        DB_POOL_WAITING.dec();
+    }
+}
+
+///
+/// Encodes all the metrics in the Prometheus text format
+/// the pool gauges are sampled at scrape time
+///
+pub fn gather(pool: &PgPool) -> anyhow::Result<(String, Vec<u8>)> {
+    DB_POOL_SIZE.set(pool.size() as i64);
+    DB_POOL_IDLE.set(pool.idle() as i64);
+    Lazy::force(&DB_POOL_WAITING);
+    Lazy::force(&CACHE_ENTRIES);
+
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    encoder.encode(&prometheus::gather(), &mut buffer)?;
+    Ok((encoder.format_type().to_string(), buffer))
+}
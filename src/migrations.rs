@@ -0,0 +1,106 @@
+// src/migrations.rs
+
+use sqlx::postgres::PgRow;
+use sqlx::{Executor, PgPool, Row};
+
+///
+/// The migrations embedded in the binary, in the order they must be applied
+/// the version is the file name without the extension
+///
+const MIGRATIONS: &[(&str, &str)] = &[
+    (
+        "0001_create_persons",
+        include_str!("../migrations/0001_create_persons.sql"),
+    ),
+    (
+        "0002_notify_persons_changes",
+        include_str!("../migrations/0002_notify_persons_changes.sql"),
+    ),
+    (
+        "0003_create_webhooks",
+        include_str!("../migrations/0003_create_webhooks.sql"),
+    ),
+    (
+        "0004_create_jobs",
+        include_str!("../migrations/0004_create_jobs.sql"),
+    ),
+    (
+        "0005_persons_updated_at",
+        include_str!("../migrations/0005_persons_updated_at.sql"),
+    ),
+    (
+        "0006_person_duplicates",
+        include_str!("../migrations/0006_person_duplicates.sql"),
+    ),
+    (
+        "0007_notify_persons_ids",
+        include_str!("../migrations/0007_notify_persons_ids.sql"),
+    ),
+    (
+        "0008_notify_persons_truncate",
+        include_str!("../migrations/0008_notify_persons_truncate.sql"),
+    ),
+];
+
+async fn create_migrations_table(pool: &PgPool) -> anyhow::Result<()> {
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS _migrations (
+                version TEXT PRIMARY KEY,
+                applied_at TIMESTAMPTZ NOT NULL DEFAULT now()
+            );",
+    )
+    .execute(&mut tx)
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+async fn applied_versions(pool: &PgPool) -> anyhow::Result<Vec<String>> {
+    let mut tx = pool.begin().await?;
+    let versions = sqlx::query("SELECT version FROM _migrations ORDER BY version;")
+        .map(|row: PgRow| row.get(0))
+        .fetch_all(&mut tx)
+        .await?;
+    tx.commit().await?;
+    Ok(versions)
+}
+
+///
+/// Applies the migrations not yet recorded in the `_migrations` table
+/// each migration runs in its own transaction
+/// returns the versions applied
+///
+pub async fn run(pool: &PgPool) -> anyhow::Result<Vec<&'static str>> {
+    create_migrations_table(pool).await?;
+    let applied = applied_versions(pool).await?;
+
+    let mut newly_applied = Vec::new();
+    for (version, sql) in MIGRATIONS {
+        if applied.iter().any(|v| v == version) {
+            continue;
+        }
+        tracing::info!("MIGRATIONS : applying {}", version);
+        let mut tx = pool.begin().await?;
+        tx.execute(*sql).await?;
+        sqlx::query("INSERT INTO _migrations (version) VALUES ($1);")
+            .bind(*version)
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+        newly_applied.push(*version);
+    }
+    Ok(newly_applied)
+}
+
+///
+/// Returns the versions of the embedded migrations not applied to the database
+///
+pub async fn pending(pool: &PgPool) -> anyhow::Result<Vec<&'static str>> {
+    let applied = applied_versions(pool).await?;
+    Ok(MIGRATIONS
+        .iter()
+        .map(|(version, _)| *version)
+        .filter(|version| !applied.iter().any(|v| v == version))
+        .collect())
+}
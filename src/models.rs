@@ -1,13 +1,21 @@
 // src/models.rs
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use sqlx::{FromRow, PgPool, Row};
+use sqlx::{FromRow, Row};
+use std::collections::BTreeMap;
 use std::str::FromStr;
+use std::time::Duration;
 
 use sqlx::postgres::PgRow;
 use warp::reply::Response;
 
-#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+use crate::resource::{Invalid, Resource, Value};
+
+/// The longest first or last name accepted, in characters
+pub const MAX_NAME_LENGTH: usize = 100;
+
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow, JsonSchema)]
 pub struct InsertablePerson {
     pub first_name: String,
     pub last_name: String,
@@ -58,7 +66,7 @@ impl InsertablePerson {
 impl FromStr for InsertablePerson {
     type Err = ();
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
+    fn from_str(_s: &str) -> Result<Self, Self::Err> {
         Ok(InsertablePerson {
             first_name: "".to_string(),
             last_name: "".to_string(),
@@ -67,11 +75,90 @@ impl FromStr for InsertablePerson {
 }
 
 // this struct will be used to represent database record
-#[derive(Serialize, Deserialize, FromRow, Debug, Eq, PartialEq)]
+#[derive(Serialize, Deserialize, FromRow, Debug, Clone, Eq, PartialEq, JsonSchema)]
 pub struct Person {
     pub id: i32,
     pub first_name: String,
     pub last_name: String,
+    /// milliseconds since the epoch, none for a person not read from the table
+    #[serde(skip)]
+    pub updated_at: Option<i64>,
+}
+
+impl Resource for Person {
+    type Insertable = InsertablePerson;
+
+    const TABLE: &'static str = "persons";
+    const COLUMNS: &'static [&'static str] = &["first_name", "last_name"];
+    const UPDATED_AT: Option<&'static str> = Some("updated_at");
+    const MATCH_ON: Option<&'static str> = Some("first_name || ' ' || last_name");
+
+    const NAME: &'static str = "person";
+    const PLURAL: &'static str = "persons";
+
+    const LIST_ROUTE: &'static str = "/persons";
+    const ITEM_ROUTE: &'static str = "/persons/{id}";
+    const ADD_ROUTE: &'static str = "/add";
+
+    const LIST_TEMPLATE: &'static str = "persons.html";
+    const EDIT_TEMPLATE: &'static str = "modify_person.html";
+    const ADD_TEMPLATE: &'static str = "add_person.html";
+
+    fn id(&self) -> i32 {
+        self.id
+    }
+
+    fn updated_at(&self) -> Option<i64> {
+        self.updated_at
+    }
+
+    fn from_row(row: &PgRow) -> Person {
+        Person {
+            id: row.get(0),
+            first_name: row.get(1),
+            last_name: row.get(2),
+            updated_at: row.get(3),
+        }
+    }
+
+    fn values(person: &InsertablePerson) -> Vec<Value> {
+        vec![
+            Value::Text(person.first_name.clone()),
+            Value::Text(person.last_name.clone()),
+        ]
+    }
+
+    fn with_id(id: i32, person: InsertablePerson) -> Person {
+        Person {
+            id,
+            first_name: person.first_name,
+            last_name: person.last_name,
+            updated_at: None,
+        }
+    }
+
+    fn match_text(person: &InsertablePerson) -> String {
+        format!("{} {}", person.first_name.trim(), person.last_name.trim())
+    }
+
+    fn validate(person: &InsertablePerson) -> Result<(), Invalid> {
+        let names = [
+            ("first_name", &person.first_name),
+            ("last_name", &person.last_name),
+        ];
+        for &(field, name) in names.iter() {
+            if name.trim().is_empty() {
+                return Err(Invalid::new(field, "must not be empty"));
+            }
+            if name.chars().count() > MAX_NAME_LENGTH {
+                return Err(Invalid::new(
+                    field,
+                    &format!("must be at most {} characters", MAX_NAME_LENGTH),
+                ));
+            }
+        }
+        Ok(())
+    }
 }
 
 // si on veut une sortie String et non Json ...
@@ -93,3 +180,60 @@ impl warp::reply::Reply for InsertablePerson {
         Response::new(format!("nom: {}\n prenom: {}", self.first_name, self.last_name).into())
     }
 }
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Up,
+    Down,
+}
+
+///
+/// The state of one component checked by the readiness probe
+///
+#[derive(Serialize, Debug, JsonSchema)]
+pub struct ComponentHealth {
+    pub status: HealthStatus,
+    pub latency_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ComponentHealth {
+    pub fn up(latency: Duration) -> ComponentHealth {
+        ComponentHealth {
+            status: HealthStatus::Up,
+            latency_ms: latency.as_secs_f64() * 1000.0,
+            error: None,
+        }
+    }
+
+    pub fn down(latency: Duration, error: String) -> ComponentHealth {
+        ComponentHealth {
+            status: HealthStatus::Down,
+            latency_ms: latency.as_secs_f64() * 1000.0,
+            error: Some(error),
+        }
+    }
+}
+
+///
+/// The body of the health endpoints
+/// the status is down as soon as one component is down
+///
+#[derive(Serialize, Debug, JsonSchema)]
+pub struct HealthReport {
+    pub status: HealthStatus,
+    pub components: BTreeMap<&'static str, ComponentHealth>,
+}
+
+impl HealthReport {
+    pub fn new(components: BTreeMap<&'static str, ComponentHealth>) -> HealthReport {
+        let status = if components.values().all(|c| c.status == HealthStatus::Up) {
+            HealthStatus::Up
+        } else {
+            HealthStatus::Down
+        };
+        HealthReport { status, components }
+    }
+}
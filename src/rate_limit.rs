@@ -0,0 +1,298 @@
+// src/rate_limit.rs
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use warp::filters::BoxedFilter;
+use warp::reject::{self, Reject};
+use warp::{Filter, Reply};
+
+use crate::server::ClientAddr;
+
+/// Above this number of buckets, the full ones are dropped.
+const MAX_BUCKETS: usize = 10_000;
+/// Buckets created between two sweeps of the full ones, so a sweep is paid by many requests
+const SWEEP_EVERY: usize = 1_000;
+
+///
+/// What a client is identified by
+/// a request missing the token or the user falls back to its ip address
+///
+/// The token and the user are not verified here: a client sending a new value
+/// at every request gets a new bucket every time. Only use them behind a proxy
+/// that authenticates the requests and overwrites these headers.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyStrategy {
+    /// the client ip address
+    Ip,
+    /// the `Authorization: Bearer` or `X-Api-Token` header, checked by a trusted proxy
+    Token,
+    /// the `X-Forwarded-User` header set by a trusted authenticating proxy
+    User,
+}
+
+impl FromStr for KeyStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ip" => Ok(KeyStrategy::Ip),
+            "token" => Ok(KeyStrategy::Token),
+            "user" => Ok(KeyStrategy::User),
+            _ => Err(format!("unknown rate limit key : {}", s)),
+        }
+    }
+}
+
+///
+/// The kind of access a route gives, each kind has its own quota
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+///
+/// A token bucket quota
+/// `burst` requests at once, refilled at `per_minute` requests a minute.
+/// A quota of 0 per minute means no limit.
+///
+#[derive(Debug, Clone, Copy)]
+pub struct Quota {
+    pub per_minute: u32,
+    pub burst: u32,
+}
+
+impl Quota {
+    fn is_unlimited(&self) -> bool {
+        self.per_minute == 0
+    }
+
+    fn tokens_per_sec(&self) -> f64 {
+        f64::from(self.per_minute) / 60.0
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimitConfig {
+    pub key: KeyStrategy,
+    pub reads: Quota,
+    pub writes: Quota,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+}
+
+impl Bucket {
+    ///
+    /// True when the bucket would be full by now, forgetting it changes nothing
+    ///
+    fn is_full(&self, quota: Quota, now: Instant) -> bool {
+        let refilled =
+            self.tokens + now.duration_since(self.last).as_secs_f64() * quota.tokens_per_sec();
+        refilled >= f64::from(quota.burst.max(1))
+    }
+}
+
+#[derive(Debug, Default)]
+struct Buckets {
+    map: HashMap<(Access, String), Bucket>,
+    /// buckets created since the last sweep
+    created: usize,
+}
+
+///
+/// The rejection of a request over its quota
+/// `handle_rejection` turns it into a 429 with the rate limit headers
+///
+#[derive(Debug)]
+pub struct RateLimited {
+    limit: u32,
+    retry_after: Duration,
+}
+
+impl Reject for RateLimited {}
+
+impl RateLimited {
+    /// Whole seconds before the next request is allowed, at least 1
+    pub fn retry_after_secs(&self) -> u64 {
+        (self.retry_after.as_secs_f64().ceil() as u64).max(1)
+    }
+
+    pub fn with_headers(&self, reply: impl Reply) -> impl Reply {
+        let secs = self.retry_after_secs();
+        let reply = warp::reply::with_header(reply, "retry-after", secs.to_string());
+        let reply = warp::reply::with_header(reply, "ratelimit-limit", self.limit.to_string());
+        let reply = warp::reply::with_header(reply, "ratelimit-remaining", "0");
+        warp::reply::with_header(reply, "ratelimit-reset", secs.to_string())
+    }
+}
+
+///
+/// Token buckets per client and access kind, shared by all the routes
+///
+#[derive(Clone)]
+pub struct RateLimiter {
+    config: Arc<RateLimitConfig>,
+    buckets: Arc<Mutex<Buckets>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> RateLimiter {
+        RateLimiter {
+            config: Arc::new(config),
+            buckets: Arc::new(Mutex::new(Buckets::default())),
+        }
+    }
+
+    /// How the clients are identified
+    pub(crate) fn key_strategy(&self) -> KeyStrategy {
+        self.config.key
+    }
+
+    fn quota(&self, access: Access) -> Quota {
+        match access {
+            Access::Read => self.config.reads,
+            Access::Write => self.config.writes,
+        }
+    }
+
+    ///
+    /// Takes a token from the bucket of the client
+    ///
+    pub(crate) fn check(&self, access: Access, key: String) -> Result<(), RateLimited> {
+        let quota = self.quota(access);
+        if quota.is_unlimited() {
+            return Ok(());
+        }
+        let capacity = f64::from(quota.burst.max(1));
+        let now = Instant::now();
+
+        let mut buckets = self.buckets.lock().unwrap();
+        let key = (access, key);
+        if !buckets.map.contains_key(&key) {
+            buckets.created += 1;
+            if buckets.map.len() >= MAX_BUCKETS && buckets.created >= SWEEP_EVERY {
+                self.sweep(&mut buckets, now);
+            }
+        }
+
+        let bucket = buckets.map.entry(key).or_insert(Bucket {
+            tokens: capacity,
+            last: now,
+        });
+        let elapsed = now.duration_since(bucket.last).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * quota.tokens_per_sec()).min(capacity);
+        bucket.last = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            let missing = 1.0 - bucket.tokens;
+            Err(RateLimited {
+                limit: quota.burst,
+                retry_after: Duration::from_secs_f64(missing / quota.tokens_per_sec()),
+            })
+        }
+    }
+
+    ///
+    /// Drops the buckets that are full again, each judged by the quota of its own kind
+    ///
+    fn sweep(&self, buckets: &mut Buckets, now: Instant) {
+        let (reads, writes) = (self.config.reads, self.config.writes);
+        buckets.map.retain(|(access, _), bucket| {
+            let quota = match access {
+                Access::Read => reads,
+                Access::Write => writes,
+            };
+            !bucket.is_full(quota, now)
+        });
+        buckets.created = 0;
+    }
+
+    ///
+    /// Filter rejecting the requests over the quota of the client
+    /// add it to a route after the method and path filters,
+    /// so only the requests of that route count
+    ///
+    pub fn limit(&self, access: Access) -> BoxedFilter<()> {
+        let limiter = self.clone();
+        client_key(self.config.key)
+            .and_then(move |key: String| {
+                let res = limiter.check(access, key);
+                async move {
+                    res.map_err(|limited| {
+                        tracing::warn!("RATE LIMIT : {:?} quota exceeded", access);
+                        reject::custom(limited)
+                    })
+                }
+            })
+            .untuple_one()
+            .boxed()
+    }
+}
+
+///
+/// Extracts the key identifying the client
+/// its address is the `ClientAddr` of `server::bind`,
+/// or the remote address `warp::serve` knows when the app is served by another server
+///
+fn client_key(strategy: KeyStrategy) -> BoxedFilter<(String,)> {
+    let ip = warp::ext::get::<ClientAddr>()
+        .map(|client: ClientAddr| Some(client.0.ip()))
+        .or(warp::addr::remote().map(|remote: Option<SocketAddr>| remote.map(|addr| addr.ip())))
+        .unify();
+
+    warp::header::optional::<String>("authorization")
+        .and(warp::header::optional::<String>("x-api-token"))
+        .and(warp::header::optional::<String>("x-forwarded-user"))
+        .and(ip)
+        .map(
+            move |authorization: Option<String>,
+                  api_token: Option<String>,
+                  user: Option<String>,
+                  ip: Option<IpAddr>| {
+                key_of(
+                    strategy,
+                    authorization.as_deref(),
+                    api_token.as_deref(),
+                    user.as_deref(),
+                    ip,
+                )
+            },
+        )
+        .boxed()
+}
+
+///
+/// The key of a client from the `Authorization`, `X-Api-Token` and `X-Forwarded-User` values
+/// it sent, falling back to its ip address
+///
+pub(crate) fn key_of(
+    strategy: KeyStrategy,
+    authorization: Option<&str>,
+    api_token: Option<&str>,
+    user: Option<&str>,
+    ip: Option<IpAddr>,
+) -> String {
+    let bearer = authorization.and_then(|value| value.strip_prefix("Bearer "));
+    let key = match strategy {
+        KeyStrategy::Ip => None,
+        KeyStrategy::Token => bearer.or(api_token).map(|token| format!("token:{}", token)),
+        KeyStrategy::User => user.map(|user| format!("user:{}", user)),
+    };
+    key.unwrap_or_else(|| match ip {
+        Some(ip) => format!("ip:{}", ip),
+        None => "ip:unknown".to_string(),
+    })
+}
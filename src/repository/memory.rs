@@ -0,0 +1,89 @@
+// src/repository/memory.rs
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+
+use crate::resource::Resource;
+
+use super::Repository;
+
+struct Store<T> {
+    items: BTreeMap<i32, T>,
+    last_id: i32,
+}
+
+///
+/// The rows kept in memory, ordered by id like the table
+/// for the tests and for running without a database
+///
+pub struct MemoryRepository<T> {
+    store: Arc<Mutex<Store<T>>>,
+}
+
+impl<T> MemoryRepository<T> {
+    pub fn new() -> MemoryRepository<T> {
+        MemoryRepository {
+            store: Arc::new(Mutex::new(Store {
+                items: BTreeMap::new(),
+                last_id: 0,
+            })),
+        }
+    }
+}
+
+impl<T> Default for MemoryRepository<T> {
+    fn default() -> Self {
+        MemoryRepository::new()
+    }
+}
+
+impl<T> Clone for MemoryRepository<T> {
+    fn clone(&self) -> Self {
+        MemoryRepository {
+            store: self.store.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl<T: Resource> Repository<T> for MemoryRepository<T> {
+    async fn list(&self) -> anyhow::Result<Vec<T>> {
+        let store = self.store.lock().unwrap();
+        Ok(store.items.values().cloned().collect())
+    }
+
+    async fn find(&self, id: i32) -> anyhow::Result<T> {
+        let store = self.store.lock().unwrap();
+        store
+            .items
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| anyhow!("no {} with id {}", T::NAME, id))
+    }
+
+    async fn add(&self, item: T::Insertable) -> anyhow::Result<T> {
+        let mut store = self.store.lock().unwrap();
+        store.last_id += 1;
+        let item = T::with_id(store.last_id, item);
+        store.items.insert(item.id(), item.clone());
+        Ok(item)
+    }
+
+    async fn update(&self, id: i32, item: T::Insertable) -> anyhow::Result<T> {
+        let mut store = self.store.lock().unwrap();
+        let stored = store
+            .items
+            .get_mut(&id)
+            .ok_or_else(|| anyhow!("no {} with id {}", T::NAME, id))?;
+        *stored = T::with_id(id, item);
+        Ok(stored.clone())
+    }
+
+    async fn delete(&self, id: i32) -> anyhow::Result<i32> {
+        let mut store = self.store.lock().unwrap();
+        Ok(store.items.remove(&id).map_or(0, |_| 1))
+    }
+}
@@ -0,0 +1,73 @@
+// src/repository/mod.rs
+
+use anyhow::bail;
+use async_trait::async_trait;
+
+use crate::models::Person;
+use crate::resource::{Duplicate, ListVersion, Resource};
+
+mod memory;
+mod postgres;
+
+pub use memory::MemoryRepository;
+pub use postgres::PgRepository;
+
+pub type PgPersonRepository = PgRepository<Person>;
+pub type MemoryPersonRepository = MemoryRepository<Person>;
+
+///
+/// The storage of the rows of a resource
+/// the handlers only know this trait, so the HTTP layer runs
+/// against Postgres as well as against the in-memory store
+///
+#[async_trait]
+pub trait Repository<T: Resource>: Clone + Send + Sync + 'static {
+    async fn list(&self) -> anyhow::Result<Vec<T>>;
+
+    async fn find(&self, id: i32) -> anyhow::Result<T>;
+
+    async fn add(&self, item: T::Insertable) -> anyhow::Result<T>;
+
+    async fn update(&self, id: i32, item: T::Insertable) -> anyhow::Result<T>;
+
+    /// Returns the number of rows deleted
+    async fn delete(&self, id: i32) -> anyhow::Result<i32>;
+
+    ///
+    /// The version of the list, none when the rows do not know their update time
+    /// derived from the whole list unless the storage has a cheaper way
+    ///
+    async fn version(&self) -> anyhow::Result<Option<ListVersion>> {
+        Ok(ListVersion::of(&self.list().await?))
+    }
+
+    ///
+    /// The rows likely to be the same as `item`, with their similarity
+    /// none unless the storage can compare texts
+    ///
+    async fn similar(&self, _item: &T::Insertable) -> anyhow::Result<Vec<(T, f32)>> {
+        Ok(Vec::new())
+    }
+
+    /// The pairs of rows at least `threshold` similar
+    async fn duplicates(&self, _threshold: f32) -> anyhow::Result<Vec<Duplicate<T>>> {
+        Ok(Vec::new())
+    }
+
+    /// Deletes `remove`, its id now referring to `keep`; returns `keep`
+    async fn merge(&self, _keep: i32, _remove: i32) -> anyhow::Result<T> {
+        bail!("merging {} is not supported by this storage", T::PLURAL)
+    }
+
+    /// The row a merged id now refers to
+    async fn merged_into(&self, _id: i32) -> anyhow::Result<Option<i32>> {
+        Ok(None)
+    }
+}
+
+///
+/// The storage of the persons
+///
+pub trait PersonRepository: Repository<Person> {}
+
+impl<R: Repository<Person>> PersonRepository for R {}
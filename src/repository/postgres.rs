@@ -0,0 +1,137 @@
+// src/repository/postgres.rs
+
+use std::future::Future;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use sqlx::PgPool;
+
+use crate::cache::{Cache, Key};
+use crate::db;
+use crate::resource::{Duplicate, ListVersion, Resource, DUPLICATE_THRESHOLD};
+
+use super::Repository;
+
+///
+/// The rows stored in Postgres, through the `db` queries
+/// the lookups and lists are served from `cache` when there is one
+///
+pub struct PgRepository<T> {
+    pool: PgPool,
+    cache: Option<Arc<Cache>>,
+    resource: PhantomData<fn() -> T>,
+}
+
+impl<T> PgRepository<T> {
+    pub fn new(pool: PgPool) -> PgRepository<T> {
+        PgRepository {
+            pool,
+            cache: None,
+            resource: PhantomData,
+        }
+    }
+
+    ///
+    /// Serves the lookups and lists from `cache`,
+    /// forgetting a row there as soon as a write of this repository is committed
+    ///
+    pub fn with_cache(mut self, cache: Option<Arc<Cache>>) -> Self {
+        self.cache = cache;
+        self
+    }
+}
+
+impl<T> Clone for PgRepository<T> {
+    fn clone(&self) -> Self {
+        PgRepository::new(self.pool.clone()).with_cache(self.cache.clone())
+    }
+}
+
+impl<T: Resource> PgRepository<T> {
+    ///
+    /// The cached result of `query` under `key`, or the result of running it
+    ///
+    async fn cached<V, F>(&self, key: Key, query: F) -> anyhow::Result<V>
+    where
+        V: Clone + Send + Sync + 'static,
+        F: Future<Output = anyhow::Result<V>>,
+    {
+        let cache = match &self.cache {
+            Some(cache) => cache,
+            None => return query.await,
+        };
+        if let Some(value) = cache.get::<V>(&key) {
+            return Ok(value);
+        }
+        let generation = cache.generation();
+        let value = query.await?;
+        cache.insert(generation, key, value.clone());
+        Ok(value)
+    }
+
+    ///
+    /// Forgets a changed row in the cache as soon as it is committed,
+    /// so the page following a write never shows the old row;
+    /// the other instances are told by the notification sent from the `db::*_in` function
+    ///
+    fn forget(&self, id: i32) {
+        if let Some(cache) = &self.cache {
+            cache.invalidate(T::TABLE, id);
+        }
+    }
+}
+
+#[async_trait]
+impl<T: Resource> Repository<T> for PgRepository<T> {
+    async fn list(&self) -> anyhow::Result<Vec<T>> {
+        self.cached(Key::list(T::TABLE), db::list::<T>(&self.pool))
+            .await
+    }
+
+    async fn find(&self, id: i32) -> anyhow::Result<T> {
+        self.cached(Key::item(T::TABLE, id), db::find::<T>(id, &self.pool))
+            .await
+    }
+
+    async fn add(&self, item: T::Insertable) -> anyhow::Result<T> {
+        let item = db::add::<T>(&self.pool, item).await?;
+        self.forget(item.id());
+        Ok(item)
+    }
+
+    async fn update(&self, id: i32, item: T::Insertable) -> anyhow::Result<T> {
+        let item = db::update::<T>(id, item, &self.pool).await?;
+        self.forget(id);
+        Ok(item)
+    }
+
+    async fn delete(&self, id: i32) -> anyhow::Result<i32> {
+        let deleted = db::delete::<T>(id, &self.pool).await?;
+        self.forget(id);
+        Ok(deleted)
+    }
+
+    async fn version(&self) -> anyhow::Result<Option<ListVersion>> {
+        db::version::<T>(&self.pool).await
+    }
+
+    async fn similar(&self, item: &T::Insertable) -> anyhow::Result<Vec<(T, f32)>> {
+        db::similar::<T>(item, DUPLICATE_THRESHOLD, &self.pool).await
+    }
+
+    async fn duplicates(&self, threshold: f32) -> anyhow::Result<Vec<Duplicate<T>>> {
+        db::duplicates::<T>(threshold, &self.pool).await
+    }
+
+    async fn merge(&self, keep: i32, remove: i32) -> anyhow::Result<T> {
+        let kept = db::merge::<T>(keep, remove, &self.pool).await?;
+        self.forget(keep);
+        self.forget(remove);
+        Ok(kept)
+    }
+
+    async fn merged_into(&self, id: i32) -> anyhow::Result<Option<i32>> {
+        db::merged_into::<T>(id, &self.pool).await
+    }
+}
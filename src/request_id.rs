@@ -0,0 +1,99 @@
+// src/request_id.rs
+
+use std::cell::RefCell;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use warp::http::header::{HeaderMap, HeaderValue};
+
+pub const HEADER: &str = "x-request-id";
+
+///
+/// The id correlating the logs, the response and the error body of a request
+/// taken from the `X-Request-Id` header when the client sends a sane one,
+/// generated otherwise
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(String);
+
+impl RequestId {
+    pub fn from_headers(headers: &HeaderMap) -> RequestId {
+        headers
+            .get(HEADER)
+            .and_then(|value| value.to_str().ok())
+            .filter(|id| is_valid(id))
+            .map(|id| RequestId(id.to_string()))
+            .unwrap_or_else(RequestId::generate)
+    }
+
+    pub fn generate() -> RequestId {
+        RequestId(uuid::Uuid::new_v4().to_string())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn header_value(&self) -> HeaderValue {
+        // only visible ascii gets here, see `is_valid`
+        HeaderValue::from_str(&self.0).unwrap()
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+///
+/// Accepts the ids a client may reasonably send,
+/// so nothing odd ends up in the logs or the response headers
+///
+fn is_valid(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= 128
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<RequestId>> = RefCell::new(None);
+}
+
+///
+/// Returns the id of the request being handled, if any
+///
+pub fn current() -> Option<RequestId> {
+    CURRENT.with(|current| current.borrow().clone())
+}
+
+///
+/// Makes `id` the current request id while `fut` is polled
+///
+pub fn scope<F: Future>(id: RequestId, fut: F) -> Scoped<F> {
+    Scoped {
+        id,
+        inner: Box::pin(fut),
+    }
+}
+
+pub struct Scoped<F> {
+    id: RequestId,
+    inner: Pin<Box<F>>,
+}
+
+impl<F: Future> Future for Scoped<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        let this = &mut *self;
+        let previous = CURRENT.with(|current| current.replace(Some(this.id.clone())));
+        let res = this.inner.as_mut().poll(cx);
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+        res
+    }
+}
@@ -0,0 +1,328 @@
+// src/resource.rs
+
+use std::fmt;
+
+use serde::de::DeserializeOwned;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgRow;
+use warp::reject::Reject;
+
+///
+/// A value written in a column, bound to its `$n` parameter
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Text(String),
+    Int(i32),
+    Bool(bool),
+}
+
+///
+/// A field refused by `Resource::validate`, answered with a 400
+///
+#[derive(Debug)]
+pub struct Invalid {
+    pub field: &'static str,
+    pub message: String,
+}
+
+impl Invalid {
+    pub fn new(field: &'static str, message: &str) -> Invalid {
+        Invalid {
+            field,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Invalid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.field, self.message)
+    }
+}
+
+impl Reject for Invalid {}
+
+///
+/// A table managed through the generic CRUD routes
+/// the queries (`db`), the repositories, the handlers and the filters
+/// are all derived from this description.
+///
+/// The table has a `SERIAL` primary key named `id`; the rows are read as
+/// `id` followed by `COLUMNS`, in that order, by `from_row`, then the
+/// `UPDATED_AT` column if any, in milliseconds since the epoch.
+///
+pub trait Resource: Serialize + Clone + Send + Sync + Unpin + 'static {
+    /// The form posted to create or update a row, one field per column
+    type Insertable: DeserializeOwned + Clone + Send + Sync + 'static;
+
+    /// Name of the table
+    const TABLE: &'static str;
+    /// The columns written from the insertable, in the order of `values`
+    const COLUMNS: &'static [&'static str];
+    /// The `TIMESTAMPTZ` column the table keeps up to date on every write,
+    /// the responses are then conditional on it
+    const UPDATED_AT: Option<&'static str> = None;
+    /// The text compared by trigram similarity to find duplicates,
+    /// an SQL expression over the columns, `first_name || ' ' || last_name`
+    const MATCH_ON: Option<&'static str> = None;
+
+    /// Name of one row in the templates, `person`
+    const NAME: &'static str;
+    /// Name of the rows in the templates, `persons`
+    const PLURAL: &'static str;
+
+    /// Route template of the list, `/persons`
+    const LIST_ROUTE: &'static str;
+    /// Route template of one row, ending with `{id}`, `/persons/{id}`
+    const ITEM_ROUTE: &'static str;
+    /// Route template of the creation page and form, `/add`
+    const ADD_ROUTE: &'static str;
+
+    const LIST_TEMPLATE: &'static str;
+    const EDIT_TEMPLATE: &'static str;
+    const ADD_TEMPLATE: &'static str;
+
+    fn id(&self) -> i32;
+
+    /// When the row was last written, in milliseconds since the epoch
+    fn updated_at(&self) -> Option<i64> {
+        None
+    }
+
+    /// Reads a row selected as `id, COLUMNS...`
+    fn from_row(row: &PgRow) -> Self;
+
+    /// The values of `COLUMNS`, in the same order
+    fn values(item: &Self::Insertable) -> Vec<Value>;
+
+    /// The row `item` gives once stored under `id`
+    fn with_id(id: i32, item: Self::Insertable) -> Self;
+
+    /// The text of a form compared to `MATCH_ON`
+    fn match_text(_item: &Self::Insertable) -> String {
+        String::new()
+    }
+
+    /// Checks a form before it reaches the database
+    fn validate(_item: &Self::Insertable) -> Result<(), Invalid> {
+        Ok(())
+    }
+}
+
+/// The similarity from which two rows are reported as likely duplicates
+pub const DUPLICATE_THRESHOLD: f32 = 0.6;
+
+/// The most duplicates reported for a row, the closest ones
+pub const DUPLICATES_PER_ROW: usize = 5;
+
+///
+/// Two rows likely to be the same, by the similarity of their `MATCH_ON` text
+///
+#[derive(Serialize, Debug, Clone, JsonSchema)]
+pub struct Duplicate<T> {
+    pub first: T,
+    pub second: T,
+    /// from 0, nothing in common, to 1, the same text
+    pub similarity: f32,
+}
+
+///
+/// The query of the duplicates report
+///
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct DuplicatesQuery {
+    pub threshold: Option<f32>,
+}
+
+///
+/// The body merging the row `from` into the row of the route
+///
+#[derive(Deserialize, Debug, Clone, JsonSchema)]
+pub struct MergeRequest {
+    pub from: i32,
+}
+
+///
+/// The state of the rows of a resource, changing with every write:
+/// their number and the latest `UPDATED_AT`
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ListVersion {
+    pub count: i64,
+    pub updated_at: Option<i64>,
+}
+
+impl ListVersion {
+    ///
+    /// The version of `items`, none when one of them does not know its update time
+    ///
+    pub fn of<T: Resource>(items: &[T]) -> Option<ListVersion> {
+        let mut updated_at = None;
+        for item in items {
+            updated_at = updated_at.max(Some(item.updated_at()?));
+        }
+        Some(ListVersion {
+            count: items.len() as i64,
+            updated_at,
+        })
+    }
+}
+
+///
+/// The SQL of a resource, built from its table and columns
+///
+pub struct Statements {
+    pub list: String,
+    pub find: String,
+    pub insert: String,
+    pub update: String,
+    pub delete: String,
+    /// the count and the latest update, when the table keeps `UPDATED_AT`
+    pub version: Option<String>,
+    /// the rows similar to a text, then the pairs of similar rows, when there is a `MATCH_ON`
+    pub similar: Option<String>,
+    pub duplicates: Option<String>,
+}
+
+impl Statements {
+    pub fn of<T: Resource>() -> Statements {
+        let columns = T::COLUMNS.join(", ");
+        let returning = returning::<T>();
+        let placeholders: Vec<String> = (1..=T::COLUMNS.len()).map(|n| format!("${}", n)).collect();
+        let assignments: Vec<String> = T::COLUMNS
+            .iter()
+            .zip(&placeholders)
+            .map(|(column, placeholder)| format!("{} = {}", column, placeholder))
+            .collect();
+
+        Statements {
+            list: format!("SELECT {} FROM {} ORDER BY id;", returning, T::TABLE),
+            find: format!("SELECT {} FROM {} WHERE id = $1;", returning, T::TABLE),
+            insert: format!(
+                "INSERT INTO {} ({}) VALUES ({}) RETURNING {};",
+                T::TABLE,
+                columns,
+                placeholders.join(", "),
+                returning
+            ),
+            update: format!(
+                "UPDATE {} SET {} WHERE id = ${} RETURNING {};",
+                T::TABLE,
+                assignments.join(", "),
+                T::COLUMNS.len() + 1,
+                returning
+            ),
+            delete: format!(
+                "DELETE FROM {} WHERE id = $1 RETURNING {};",
+                T::TABLE,
+                returning
+            ),
+            // `%` finds the candidates with the trigram index,
+            // from the threshold `db` sets in `pg_trgm.similarity_threshold` first
+            similar: T::MATCH_ON.map(|text| {
+                format!(
+                    "SELECT {returning}, similarity(lower({text}), lower($1)) AS score
+                        FROM {table}
+                        WHERE lower({text}) % lower($1) AND similarity(lower({text}), lower($1)) >= $2
+                        ORDER BY score DESC, id
+                        LIMIT 10;",
+                    returning = returning,
+                    text = text,
+                    table = T::TABLE
+                )
+            }),
+            // each row looks up its closest followers with the trigram index,
+            // instead of being compared to every other row
+            duplicates: T::MATCH_ON.map(|text| {
+                format!(
+                    "SELECT a.id, b.id, b.score
+                        FROM (SELECT id, lower({text}) AS text FROM {table}) a
+                        CROSS JOIN LATERAL (
+                            SELECT id, similarity(lower({text}), a.text) AS score
+                                FROM {table}
+                                WHERE lower({text}) % a.text AND id > a.id
+                                ORDER BY score DESC, id
+                                LIMIT {per_row}
+                        ) b
+                        WHERE b.score >= $1
+                        ORDER BY b.score DESC, a.id, b.id
+                        LIMIT 100;",
+                    text = text,
+                    table = T::TABLE,
+                    per_row = DUPLICATES_PER_ROW
+                )
+            }),
+            version: T::UPDATED_AT.map(|updated_at| {
+                format!(
+                    "SELECT count(*), {} FROM {};",
+                    millis(&format!("max({})", updated_at)),
+                    T::TABLE
+                )
+            }),
+        }
+    }
+
+    ///
+    /// The count of the rows whose `columns` contain a text, whatever the case,
+    /// then one page of these rows ordered by id
+    /// the `ILIKE` patterns are the first parameters, the limit and the offset the last two
+    ///
+    pub fn search<T: Resource>(columns: &[&str]) -> (String, String) {
+        let conditions: Vec<String> = columns
+            .iter()
+            .enumerate()
+            .map(|(n, column)| format!("{} ILIKE ${} ESCAPE '\\'", column, n + 1))
+            .collect();
+        let filter = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!(" WHERE {}", conditions.join(" AND "))
+        };
+        let count = format!("SELECT count(*) FROM {}{};", T::TABLE, filter);
+        let page = format!(
+            "SELECT {} FROM {}{} ORDER BY id LIMIT ${} OFFSET ${};",
+            returning::<T>(),
+            T::TABLE,
+            filter,
+            columns.len() + 1,
+            columns.len() + 2
+        );
+        (count, page)
+    }
+}
+
+///
+/// The `ILIKE` pattern of the texts containing `part`,
+/// its `%`, `_` and `\` taken literally
+///
+pub fn contains_pattern(part: &str) -> String {
+    let mut pattern = String::from("%");
+    for c in part.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
+///
+/// The columns read back from a row, with the update time last if any
+///
+fn returning<T: Resource>() -> String {
+    let columns = T::COLUMNS.join(", ");
+    match T::UPDATED_AT {
+        Some(updated_at) => format!("id, {}, {}", columns, millis(updated_at)),
+        None => format!("id, {}", columns),
+    }
+}
+
+///
+/// A timestamp as milliseconds since the epoch
+///
+fn millis(timestamp: &str) -> String {
+    format!("(extract(epoch from {}) * 1000)::bigint", timestamp)
+}
@@ -0,0 +1,12 @@
+// src/retry.rs
+
+use std::time::Duration;
+
+///
+/// The delay before the attempt following the `attempts`th one
+/// `base` doubled at every attempt, never more than `max`
+///
+pub fn backoff(base: Duration, max: Duration, attempts: i32) -> Duration {
+    let factor = 2u32.saturating_pow(attempts.max(1) as u32 - 1);
+    base.checked_mul(factor).map_or(max, |delay| delay.min(max))
+}
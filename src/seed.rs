@@ -0,0 +1,218 @@
+// src/seed.rs
+
+use sqlx::PgPool;
+
+use crate::db;
+use crate::models::{InsertablePerson, Person};
+
+///
+/// A named list of persons, loaded in this order
+/// into a fresh table they get the ids 1, 2, ...
+///
+pub struct FixtureSet {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub persons: &'static [(&'static str, &'static str)],
+}
+
+impl FixtureSet {
+    pub fn persons(&self) -> Vec<InsertablePerson> {
+        self.persons
+            .iter()
+            .map(|(first_name, last_name)| InsertablePerson {
+                first_name: first_name.to_string(),
+                last_name: last_name.to_string(),
+            })
+            .collect()
+    }
+}
+
+pub const FIXTURE_SETS: &[FixtureSet] = &[
+    FixtureSet {
+        name: "basic",
+        description: "the three persons every test starts with",
+        persons: &[
+            ("James", "ANDERSON"),
+            ("Léon", "GENGOUX"),
+            ("Joseph", "DENEUX"),
+        ],
+    },
+    FixtureSet {
+        name: "duplicates",
+        description: "pairs of persons entered twice, for the duplicates report",
+        persons: &[
+            ("Léon", "GENGOUX"),
+            ("Leon", "GENGOUX"),
+            ("Hélène", "LEFÈVRE"),
+            ("Helene", "LEFEVRE"),
+            ("Joseph", "DENEUX"),
+            ("Josef", "DENEUX"),
+            ("Amélie", "NÚÑEZ"),
+        ],
+    },
+    FixtureSet {
+        name: "demo",
+        description: "a dozen persons with names from several languages",
+        persons: &[
+            ("Léon", "GENGOUX"),
+            ("Zoé", "FONTAINE"),
+            ("François", "MERCIER"),
+            ("Anaïs", "BRUNEAU"),
+            ("Søren", "KIERKEGAARD"),
+            ("Łukasz", "NOWAK"),
+            ("José", "GARCÍA"),
+            ("Björn", "ANDERSSON"),
+            ("Chloé", "DUPONT"),
+            ("Jürgen", "MÜLLER"),
+            ("Inès", "BENALI"),
+            ("Noémie", "LAMBERT"),
+        ],
+    },
+];
+
+const FIRST_NAMES: &[&str] = &[
+    "Léon",
+    "Zoé",
+    "Chloé",
+    "Hélène",
+    "Jérôme",
+    "Amélie",
+    "François",
+    "Anaïs",
+    "Noémie",
+    "Benoît",
+    "Gaëlle",
+    "Joël",
+    "Loïc",
+    "Mathéo",
+    "Inès",
+    "Céline",
+    "José",
+    "Björn",
+    "Søren",
+    "Łukasz",
+    "Jürgen",
+    "Renée",
+    "James",
+    "Joseph",
+    "Marie",
+    "Louis",
+    "Emma",
+    "Lucas",
+    "Camille",
+    "Hugo",
+    "Alice",
+    "Nathan",
+    "Sarah",
+    "Paul",
+    "Julie",
+    "Thomas",
+    "Laura",
+    "Arthur",
+    "Manon",
+    "Victor",
+];
+
+const LAST_NAMES: &[&str] = &[
+    "GENGOUX",
+    "DENEUX",
+    "ANDERSON",
+    "LEFÈVRE",
+    "MÜLLER",
+    "NÚÑEZ",
+    "GARCÍA",
+    "DUPONT",
+    "MARTIN",
+    "BERNARD",
+    "DUBOIS",
+    "THOMAS",
+    "ROBERT",
+    "PETIT",
+    "DURAND",
+    "LEROY",
+    "MOREAU",
+    "SIMON",
+    "LAURENT",
+    "MICHEL",
+    "FONTAINE",
+    "MERCIER",
+    "BRUNEAU",
+    "LAMBERT",
+    "FRANÇOIS",
+    "GIRAUD",
+    "BOUCHÉ",
+    "CHÂTEAU",
+    "NOWAK",
+    "ANDERSSON",
+    "JANSSENS",
+    "PEETERS",
+    "VAN DAMME",
+    "D'HONDT",
+];
+
+///
+/// The fixture set called `name`
+///
+pub fn fixtures(name: &str) -> Option<&'static FixtureSet> {
+    FIXTURE_SETS.iter().find(|set| set.name == name)
+}
+
+///
+/// `count` persons picked from the name lists,
+/// always the same ones for the same `seed`, on every platform
+///
+pub fn generate(count: usize, seed: u64) -> Vec<InsertablePerson> {
+    let mut rng = SplitMix64(seed);
+    (0..count)
+        .map(|_| InsertablePerson {
+            first_name: rng.pick(FIRST_NAMES).to_string(),
+            last_name: rng.pick(LAST_NAMES).to_string(),
+        })
+        .collect()
+}
+
+///
+/// Adds `persons` in one transaction, in their order
+/// the table must be empty, unless `reset` empties it first and restarts the ids at 1;
+/// no webhook is called for them
+///
+pub async fn load(
+    pool: &PgPool,
+    persons: Vec<InsertablePerson>,
+    reset: bool,
+) -> anyhow::Result<Vec<Person>> {
+    let mut tx = db::begin(pool).await?;
+    if reset {
+        db::reset_in::<Person>(&mut tx).await?;
+    } else if !db::is_empty_in::<Person>(&mut tx).await? {
+        anyhow::bail!("the persons table is not empty, reset it to seed it");
+    }
+
+    let mut added = Vec::with_capacity(persons.len());
+    for person in persons {
+        added.push(db::insert_in::<Person>(&mut tx, person).await?);
+    }
+    tx.commit().await?;
+    tracing::info!("SEED : {} persons added", added.len());
+    Ok(added)
+}
+
+///
+/// A small generator whose sequence depends only on its seed,
+/// unlike the ones of `rand` that may change between versions
+///
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[(self.next() % items.len() as u64) as usize]
+    }
+}
@@ -0,0 +1,252 @@
+// src/server.rs
+
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use futures::future::Shared;
+use hyper::server::accept;
+use hyper::server::conn::AddrStream;
+use hyper::service::{make_service_fn, service_fn, Service};
+use hyper::{Body, Request, Response, Server, StatusCode};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_rustls::server::TlsStream;
+use tracing_futures::Instrument;
+use warp::filters::BoxedFilter;
+use warp::Reply;
+
+use crate::config::TlsConfig;
+use crate::request_id::{self, RequestId};
+use crate::tls;
+
+/// A TLS handshake not done within this delay is dropped.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+///
+/// The address of the client, put in the request extensions
+/// read it with `warp::ext::get::<ClientAddr>()`
+///
+#[derive(Debug, Clone, Copy)]
+pub struct ClientAddr(pub SocketAddr);
+
+///
+/// Binds the server on `addr`
+/// every request runs in a span carrying its method, path and request id,
+/// and the request id is echoed in the `X-Request-Id` response header.
+/// The server stops accepting connections when `signal` completes
+/// and the returned future resolves once the in-flight requests are done.
+///
+pub fn bind<R>(
+    filter: BoxedFilter<(R,)>,
+    addr: SocketAddr,
+    signal: impl Future<Output = ()> + Send + 'static,
+) -> hyper::Result<(SocketAddr, impl Future<Output = hyper::Result<()>>)>
+where
+    R: Reply + 'static,
+{
+    let make_svc = make_service_fn(move |conn: &AddrStream| {
+        let client = ClientAddr(conn.remote_addr());
+        let svc = warp::service(filter.clone());
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| handle(svc.clone(), client, req)))
+        }
+    });
+
+    let server = Server::try_bind(&addr)?.serve(make_svc);
+    let addr = server.local_addr();
+    Ok((addr, server.with_graceful_shutdown(signal)))
+}
+
+///
+/// Same as `bind`, over TLS
+///
+pub fn bind_tls<R, S>(
+    filter: BoxedFilter<(R,)>,
+    addr: SocketAddr,
+    config: &TlsConfig,
+    signal: Shared<S>,
+) -> anyhow::Result<(SocketAddr, impl Future<Output = hyper::Result<()>>)>
+where
+    R: Reply + 'static,
+    S: Future<Output = ()> + Send + 'static,
+{
+    let (addr, rx) = tls_incoming(addr, config, signal.clone())?;
+
+    let make_svc = make_service_fn(move |conn: &TlsStream<TcpStream>| {
+        let client = ClientAddr(
+            conn.get_ref()
+                .0
+                .peer_addr()
+                .unwrap_or_else(|_| ([0, 0, 0, 0], 0).into()),
+        );
+        let svc = warp::service(filter.clone());
+        async move { Ok::<_, Infallible>(service_fn(move |req| handle(svc.clone(), client, req))) }
+    });
+
+    let server = Server::builder(accept::from_stream(rx))
+        .serve(make_svc)
+        .with_graceful_shutdown(signal);
+    Ok((addr, server))
+}
+
+///
+/// The connections accepted on `addr` once their TLS handshake is done,
+/// until `stop` completes
+/// the handshakes run in their own tasks so a slow client does not hold the others
+///
+pub fn tls_incoming<S>(
+    addr: SocketAddr,
+    config: &TlsConfig,
+    stop: Shared<S>,
+) -> anyhow::Result<(
+    SocketAddr,
+    mpsc::Receiver<std::io::Result<TlsStream<TcpStream>>>,
+)>
+where
+    S: Future<Output = ()> + Send + 'static,
+{
+    let acceptor = tls::acceptor(config)?;
+    let std_listener = std::net::TcpListener::bind(addr)?;
+    std_listener.set_nonblocking(true)?;
+    let mut listener = TcpListener::from_std(std_listener)?;
+    let addr = listener.local_addr()?;
+
+    let (tx, rx) = mpsc::channel::<std::io::Result<TlsStream<TcpStream>>>(128);
+    tokio::spawn(async move {
+        loop {
+            let tcp = tokio::select! {
+                _ = stop.clone() => break,
+                res = listener.accept() => match res {
+                    Ok((tcp, _)) => tcp,
+                    Err(err) => {
+                        tracing::warn!("TLS : accept error : {}", err);
+                        continue;
+                    }
+                },
+            };
+            let acceptor = acceptor.clone();
+            let mut tx = tx.clone();
+            tokio::spawn(async move {
+                match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(tcp)).await {
+                    Ok(Ok(stream)) => {
+                        let _ = tx.send(Ok(stream)).await;
+                    }
+                    Ok(Err(err)) => tracing::debug!("TLS : handshake failed : {}", err),
+                    Err(_) => tracing::debug!("TLS : handshake timed out"),
+                }
+            });
+        }
+    });
+    Ok((addr, rx))
+}
+
+///
+/// Binds a plain HTTP server redirecting every request
+/// to the same host and path over HTTPS on `https_port`
+///
+pub fn bind_redirect(
+    addr: SocketAddr,
+    https_port: u16,
+    signal: impl Future<Output = ()> + Send + 'static,
+) -> hyper::Result<(SocketAddr, impl Future<Output = hyper::Result<()>>)> {
+    let make_svc = make_service_fn(move |_conn: &AddrStream| async move {
+        Ok::<_, Infallible>(service_fn(move |req: Request<Body>| async move {
+            Ok::<_, Infallible>(redirect_to_https(&req, https_port))
+        }))
+    });
+
+    let server = Server::try_bind(&addr)?.serve(make_svc);
+    let addr = server.local_addr();
+    Ok((addr, server.with_graceful_shutdown(signal)))
+}
+
+///
+/// Removes the port from a `Host` header value, ipv6 addresses included
+///
+pub(crate) fn strip_port(host: &str) -> &str {
+    if host.ends_with(']') {
+        return host;
+    }
+    match host.rfind(':') {
+        Some(colon) => &host[..colon],
+        None => host,
+    }
+}
+
+pub(crate) fn redirect_to_https(req: &Request<Body>, https_port: u16) -> Response<Body> {
+    let host = req
+        .headers()
+        .get(hyper::header::HOST)
+        .and_then(|value| value.to_str().ok())
+        .map(strip_port);
+    let host = match host {
+        Some(host) if !host.is_empty() => host,
+        _ => {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::empty())
+                .unwrap()
+        }
+    };
+
+    let path = req
+        .uri()
+        .path_and_query()
+        .map(|path| path.as_str())
+        .unwrap_or("/");
+    let location = if https_port == 443 {
+        format!("https://{}{}", host, path)
+    } else {
+        format!("https://{}:{}{}", host, https_port, path)
+    };
+
+    Response::builder()
+        .status(StatusCode::MOVED_PERMANENTLY)
+        .header(hyper::header::LOCATION, location)
+        .body(Body::empty())
+        .unwrap_or_else(|_| {
+            let mut resp = Response::new(Body::empty());
+            *resp.status_mut() = StatusCode::BAD_REQUEST;
+            resp
+        })
+}
+
+///
+/// Runs a server in its own task, logging the error it ends with
+///
+pub fn spawn(server: impl Future<Output = hyper::Result<()>> + Send + 'static) -> JoinHandle<()> {
+    tokio::spawn(async {
+        if let Err(err) = server.await {
+            tracing::error!("SERVER : {}", err);
+        }
+    })
+}
+
+async fn handle<S>(
+    mut svc: S,
+    client: ClientAddr,
+    mut req: Request<Body>,
+) -> Result<Response<Body>, Infallible>
+where
+    S: Service<Request<Body>, Response = Response<Body>, Error = Infallible>,
+{
+    let id = RequestId::from_headers(req.headers());
+    req.extensions_mut().insert(client);
+
+    let span = tracing::info_span!(
+        "request",
+        id = %id,
+        method = %req.method(),
+        path = %req.uri().path(),
+    );
+
+    let mut resp = request_id::scope(id.clone(), svc.call(req))
+        .instrument(span)
+        .await?;
+    resp.headers_mut()
+        .insert(request_id::HEADER, id.header_value());
+    Ok(resp)
+}
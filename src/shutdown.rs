@@ -0,0 +1,42 @@
+// src/shutdown.rs
+
+use std::time::Duration;
+
+use tokio::task::JoinHandle;
+
+///
+/// Waits for SIGINT (Ctrl-C) or, on unix, SIGTERM
+///
+pub async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut sigterm = signal(SignalKind::terminate()).expect("could not listen to SIGTERM");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => tracing::info!("SHUTDOWN : SIGINT received"),
+            _ = sigterm.recv() => tracing::info!("SHUTDOWN : SIGTERM received"),
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c().await.expect("could not listen to Ctrl-C");
+        tracing::info!("SHUTDOWN : Ctrl-C received");
+    }
+}
+
+///
+/// Waits for the server to finish the in-flight requests
+/// gives up once the deadline is reached
+///
+pub async fn drain(server: JoinHandle<()>, deadline: Duration) {
+    tracing::info!(
+        "SHUTDOWN : waiting up to {:?} for in-flight requests",
+        deadline
+    );
+    match tokio::time::timeout(deadline, server).await {
+        Ok(Ok(())) => tracing::info!("SHUTDOWN : all in-flight requests completed"),
+        Ok(Err(err)) => tracing::error!("SHUTDOWN : server task failed : {}", err),
+        Err(_) => tracing::warn!("SHUTDOWN : deadline reached, dropping remaining requests"),
+    }
+}
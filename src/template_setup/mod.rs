@@ -0,0 +1,3 @@
+// src/template_setup/mod.rs
+
+pub mod tera;
@@ -0,0 +1,100 @@
+// src/template_setup/tera.rs
+
+use std::sync::RwLock;
+
+use once_cell::sync::Lazy;
+use rust_embed::RustEmbed;
+use tera::{Context, Tera};
+
+use crate::assets;
+
+///
+/// The templates folder
+/// in release builds the files are embedded in the binary,
+/// in debug builds rust-embed reads them from disk at every access
+///
+#[derive(RustEmbed)]
+#[folder = "templates/"]
+struct Templates;
+
+///
+/// The Tera instance shared by all the handlers
+/// a broken template is logged here and surfaces later as a render error
+///
+static TERA: Lazy<RwLock<Tera>> = Lazy::new(|| {
+    let tera = load_templates().unwrap_or_else(|err| {
+        tracing::error!("TERA : could not load the templates : {}", err);
+        Tera::default()
+    });
+    RwLock::new(tera)
+});
+
+///
+/// Builds a Tera instance from all the files of the templates folder
+/// `add_raw_templates` resolves the `{% extends %}` order itself
+///
+fn load_templates() -> tera::Result<Tera> {
+    let mut templates = Vec::new();
+    for name in Templates::iter() {
+        let file = Templates::get(&name).unwrap();
+        let content = String::from_utf8_lossy(&file).into_owned();
+        templates.push((name.into_owned(), content));
+    }
+
+    let mut tera = Tera::default();
+    tera.add_raw_templates(templates)?;
+    tera.register_function("asset_url", assets::asset_url);
+    Ok(tera)
+}
+
+///
+/// Renders a template with the given context
+/// in debug builds the templates are reloaded first if a file changed on disk.
+/// The pages get `base_path`, the prefix of their links, from `filters::with_page_context`
+///
+pub fn render(name: &str, ctx: &Context) -> tera::Result<String> {
+    #[cfg(debug_assertions)]
+    hot_reload::reload_if_changed()?;
+
+    TERA.read().unwrap().render(name, ctx)
+}
+
+#[cfg(debug_assertions)]
+mod hot_reload {
+    use std::path::Path;
+    use std::sync::Mutex;
+    use std::time::SystemTime;
+
+    use once_cell::sync::Lazy;
+
+    use super::{load_templates, TERA};
+
+    static LAST_MODIFIED: Lazy<Mutex<Option<SystemTime>>> = Lazy::new(|| Mutex::new(None));
+
+    ///
+    /// Returns the most recent modification time of the files in the templates folder
+    ///
+    fn last_modified() -> Option<SystemTime> {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("templates");
+        std::fs::read_dir(dir)
+            .ok()?
+            .filter_map(|entry| entry.ok()?.metadata().ok()?.modified().ok())
+            .max()
+    }
+
+    pub fn reload_if_changed() -> tera::Result<()> {
+        let modified = last_modified();
+        let mut last = LAST_MODIFIED.lock().unwrap();
+        if last.is_none() {
+            *last = modified;
+            return Ok(());
+        }
+        if modified != *last {
+            tracing::info!("TERA : templates changed on disk, reloading");
+            let tera = load_templates()?;
+            *TERA.write().unwrap() = tera;
+            *last = modified;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,155 @@
+// src/tests/app.rs
+
+use warp::filters::BoxedFilter;
+use warp::http::StatusCode;
+use warp::reply::Response;
+use warp::test::request;
+use warp::{reject, Reply};
+
+use crate::app;
+use crate::changes::ChangeFeed;
+use crate::config::Config;
+use crate::db;
+use crate::handlers;
+use crate::repository::MemoryPersonRepository;
+use crate::resource::Invalid;
+
+///
+/// The routes mounted under `base_path` over an empty memory repository
+/// the pool opens no connection before the first query, none is made here
+///
+async fn mounted(base_path: &str) -> BoxedFilter<(Response,)> {
+    let pool = db::create_pg_pool("postgres://localhost/base_path")
+        .await
+        .unwrap();
+    let mut config = Config::from_env();
+    config.rate_limit.reads.per_minute = 0;
+    config.rate_limit.writes.per_minute = 0;
+    config.cors.allowed_origins = Vec::new();
+    config.graphiql = true;
+    app::mount(
+        base_path,
+        pool,
+        MemoryPersonRepository::new(),
+        ChangeFeed::new(),
+        &config,
+    )
+}
+
+fn body(res: &warp::http::Response<hyper::body::Bytes>) -> String {
+    String::from_utf8_lossy(res.body()).into_owned()
+}
+
+#[test]
+fn base_paths_are_normalized() {
+    assert_eq!(app::normalize("people"), "/people");
+    assert_eq!(app::normalize("/people/"), "/people");
+    assert_eq!(app::normalize("//people//team/"), "/people/team");
+    assert_eq!(app::normalize("/"), "");
+    assert_eq!(app::normalize(""), "");
+}
+
+#[tokio::test]
+async fn routes_under_a_base_path() {
+    let people = mounted("/people").await;
+
+    let res = request()
+        .method("GET")
+        .path("/people/")
+        .reply(&people)
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(body(&res).contains("href=\"/people/persons\""));
+
+    let res = request()
+        .method("GET")
+        .path("/people/persons")
+        .reply(&people)
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(body(&res).contains("href=\"/people/add\""));
+
+    let res = request()
+        .method("GET")
+        .path("/people/openapi.json")
+        .reply(&people)
+        .await;
+    assert!(body(&res).contains("\"url\":\"/people\""));
+
+    let res = request()
+        .method("GET")
+        .path("/people/graphiql")
+        .reply(&people)
+        .await;
+    assert!(body(&res).contains("/people/graphql"));
+
+    // outside of the base path, the enclosing application gets the request
+    let rejection = request()
+        .method("GET")
+        .path("/persons")
+        .filter(&people)
+        .await
+        .err()
+        .unwrap();
+    assert!(rejection.is_not_found());
+}
+
+#[tokio::test]
+async fn apps_under_different_base_paths() {
+    let people = mounted("/people").await;
+    let staff = mounted("/staff").await;
+
+    let res = request()
+        .method("GET")
+        .path("/people/")
+        .reply(&people)
+        .await;
+    assert!(body(&res).contains("href=\"/people/persons\""));
+    let res = request().method("GET").path("/staff/").reply(&staff).await;
+    assert!(body(&res).contains("href=\"/staff/persons\""));
+    assert!(!body(&res).contains("/people"));
+}
+
+#[tokio::test]
+async fn errors_are_answered_under_a_base_path() {
+    let people = mounted("/people").await;
+    let res = request()
+        .method("POST")
+        .path("/people/add")
+        .header("content-type", "application/x-www-form-urlencoded")
+        .body("first_name=&last_name=")
+        .reply(&people)
+        .await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn not_found_rejections_are_passed_along() {
+    let passed = handlers::handle_rejection_except_not_found(reject::not_found())
+        .await
+        .err()
+        .unwrap();
+    assert!(passed.is_not_found());
+
+    let answered = handlers::handle_rejection_except_not_found(reject::custom(Invalid::new(
+        "first_name",
+        "empty",
+    )))
+    .await
+    .ok()
+    .unwrap();
+    assert_eq!(answered.into_response().status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn mutations_are_refused_over_get() {
+    let app = mounted("").await;
+    let res = request()
+        .method("GET")
+        .path("/graphql?query=mutation%20%7B%20deletePerson(id%3A%201)%20%7D")
+        .reply(&app)
+        .await;
+    let refused: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+    assert!(refused["errors"][0]["message"].is_string());
+    assert!(refused["data"].is_null());
+}
@@ -0,0 +1,27 @@
+// src/tests/assets.rs
+
+use warp::http::StatusCode;
+
+use crate::assets;
+
+#[test]
+fn serves_the_static_folder() {
+    let res = assets::reply("style.css", None).unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[test]
+fn stays_inside_the_static_folder() {
+    for name in &[
+        "../Cargo.toml",
+        "..",
+        "css/../../Cargo.toml",
+        "/etc/passwd",
+        "\\..\\Cargo.toml",
+        "..\\Cargo.toml",
+        "C:/Windows/win.ini",
+        "",
+    ] {
+        assert!(assets::reply(name, None).is_none(), "{} was served", name);
+    }
+}
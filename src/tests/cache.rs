@@ -0,0 +1,96 @@
+// src/tests/cache.rs
+
+use std::time::Duration;
+
+use crate::cache::{Cache, Key};
+
+#[test]
+fn hits_misses_and_invalidation() {
+    let cache = Cache::new(10, Duration::from_secs(60));
+    assert_eq!(cache.get::<String>(&Key::item("persons", 1)), None);
+
+    let generation = cache.generation();
+    cache.insert(generation, Key::item("persons", 1), "Léon".to_string());
+    cache.insert(generation, Key::list("persons"), vec![1, 2]);
+    assert_eq!(
+        cache.get::<String>(&Key::item("persons", 1)),
+        Some("Léon".to_string())
+    );
+
+    cache.invalidate("persons", 1);
+    assert_eq!(cache.get::<String>(&Key::item("persons", 1)), None);
+    assert_eq!(cache.get::<Vec<i32>>(&Key::list("persons")), None);
+
+    let stats = cache.stats();
+    assert_eq!((stats.hits, stats.misses, stats.entries), (1, 3, 0));
+}
+
+#[test]
+fn stale_results_are_not_cached() {
+    let cache = Cache::new(10, Duration::from_secs(60));
+    // a query started before a write, finished after it
+    let generation = cache.generation();
+    cache.invalidate("persons", 1);
+    cache.insert(generation, Key::item("persons", 1), "old".to_string());
+    assert_eq!(cache.get::<String>(&Key::item("persons", 1)), None);
+}
+
+#[test]
+fn bounded_and_expiring() {
+    let cache = Cache::new(2, Duration::from_secs(60));
+    let generation = cache.generation();
+    for id in 1..=3 {
+        cache.insert(generation, Key::item("persons", id), id);
+    }
+    assert_eq!(cache.stats().entries, 2);
+    assert_eq!(cache.get::<i32>(&Key::item("persons", 1)), None);
+    assert_eq!(cache.get::<i32>(&Key::item("persons", 3)), Some(3));
+
+    let cache = Cache::new(2, Duration::from_millis(0));
+    cache.insert(cache.generation(), Key::item("persons", 1), 1);
+    assert_eq!(cache.get::<i32>(&Key::item("persons", 1)), None);
+}
+
+#[test]
+fn invalidated_keys_leave_the_order() {
+    let cache = Cache::new(2, Duration::from_secs(60));
+    cache.insert(cache.generation(), Key::item("persons", 1), 1);
+    cache.invalidate("persons", 1);
+    cache.insert(cache.generation(), Key::item("persons", 1), 1);
+    assert_eq!(cache.order(), vec![Key::item("persons", 1)]);
+
+    // a stale key in the order would evict the newest entry instead of the oldest
+    cache.insert(cache.generation(), Key::item("persons", 2), 2);
+    cache.insert(cache.generation(), Key::item("persons", 3), 3);
+    assert_eq!(cache.get::<i32>(&Key::item("persons", 1)), None);
+    assert_eq!(cache.get::<i32>(&Key::item("persons", 2)), Some(2));
+    assert_eq!(cache.get::<i32>(&Key::item("persons", 3)), Some(3));
+    assert_eq!(
+        cache.order(),
+        vec![Key::item("persons", 2), Key::item("persons", 3)]
+    );
+}
+
+#[test]
+fn expired_keys_leave_the_order() {
+    let cache = Cache::new(2, Duration::from_millis(0));
+    for _ in 0..3 {
+        cache.insert(cache.generation(), Key::item("persons", 1), 1);
+        assert_eq!(cache.get::<i32>(&Key::item("persons", 1)), None);
+    }
+    assert!(cache.order().is_empty());
+}
+
+#[test]
+fn a_table_is_invalidated_at_once() {
+    let cache = Cache::new(10, Duration::from_secs(60));
+    let generation = cache.generation();
+    cache.insert(generation, Key::item("persons", 1), 1);
+    cache.insert(generation, Key::list("persons"), vec![1]);
+    cache.insert(generation, Key::item("webhooks", 1), 1);
+
+    cache.invalidate_table("persons");
+    assert_eq!(cache.get::<i32>(&Key::item("persons", 1)), None);
+    assert_eq!(cache.get::<Vec<i32>>(&Key::list("persons")), None);
+    assert_eq!(cache.order(), vec![Key::item("webhooks", 1)]);
+}
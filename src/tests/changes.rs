@@ -0,0 +1,176 @@
+// src/tests/changes.rs
+
+use futures::{FutureExt, Stream, StreamExt};
+
+use crate::changes::{Change, ChangeFeed, Event, Op, RECENT};
+use crate::models::Person;
+
+fn change(id: i32) -> Change {
+    Change {
+        op: Op::Insert,
+        id,
+        person: Some(Person {
+            id,
+            first_name: "Léon".to_string(),
+            last_name: "GENGOUX".to_string(),
+            updated_at: Some(0),
+        }),
+    }
+}
+
+///
+/// The events ready on the stream, without waiting for the live ones
+///
+fn ready(events: &mut (impl Stream<Item = Event> + Unpin)) -> Vec<Event> {
+    let mut ready = Vec::new();
+    while let Some(Some(event)) = events.next().now_or_never() {
+        ready.push(event);
+    }
+    ready
+}
+
+///
+/// The ids of the events, `C<id>` for a change and `R<id>` for a reset
+///
+fn ids(events: &[Event]) -> Vec<String> {
+    events
+        .iter()
+        .map(|event| match event {
+            Event::Change { id, .. } => format!("C{}", id),
+            Event::Reset { id } => format!("R{}", id),
+        })
+        .collect()
+}
+
+///
+/// The epoch of the feed, read from the id of a published change
+///
+fn epoch(feed: &ChangeFeed) -> String {
+    let mut events = Box::pin(feed.subscribe(None));
+    feed.publish(change(0));
+    let id = &ids(&ready(&mut events))[0];
+    id[1..id.rfind('-').unwrap()].to_string()
+}
+
+#[test]
+fn event_ids() {
+    let id: crate::changes::EventId = "0badcafe-42".parse().unwrap();
+    assert_eq!(id.to_string(), "0badcafe-42");
+    assert!("0badcafe".parse::<crate::changes::EventId>().is_err());
+    assert!("0badcafe-x".parse::<crate::changes::EventId>().is_err());
+}
+
+#[test]
+fn live_changes() {
+    let feed = ChangeFeed::new();
+    let mut events = Box::pin(feed.subscribe(None));
+    assert!(ready(&mut events).is_empty());
+
+    feed.publish(change(1));
+    feed.publish(change(2));
+    let events = ready(&mut events);
+    assert_eq!(events.len(), 2);
+    match &events[1] {
+        Event::Change { change, .. } => assert_eq!(change.id, 2),
+        other => panic!("{:?} is not a change", other),
+    }
+}
+
+#[test]
+fn resume_from_the_backlog() {
+    let feed = ChangeFeed::new();
+    let epoch = epoch(&feed); // change 1
+    feed.publish(change(2));
+    feed.publish(change(3));
+
+    let last = format!("{}-1", epoch);
+    let mut events = Box::pin(feed.subscribe(Some(&last)));
+    assert_eq!(
+        ids(&ready(&mut events)),
+        vec![format!("C{}-2", epoch), format!("C{}-3", epoch)]
+    );
+
+    // then the live ones, each once
+    feed.publish(change(4));
+    assert_eq!(ids(&ready(&mut events)), vec![format!("C{}-4", epoch)]);
+}
+
+#[test]
+fn resume_at_the_end_of_the_backlog() {
+    let feed = ChangeFeed::new();
+    let epoch = epoch(&feed);
+    feed.publish(change(2));
+
+    let last = format!("{}-2", epoch);
+    let mut events = Box::pin(feed.subscribe(Some(&last)));
+    assert!(ready(&mut events).is_empty());
+}
+
+#[test]
+fn resume_past_the_end_of_the_backlog() {
+    let feed = ChangeFeed::new();
+    let epoch = epoch(&feed);
+    feed.publish(change(2));
+
+    // an id the feed never gave
+    let last = format!("{}-3", epoch);
+    let mut events = Box::pin(feed.subscribe(Some(&last)));
+    assert_eq!(ids(&ready(&mut events)), vec![format!("R{}-2", epoch)]);
+}
+
+#[test]
+fn resume_before_the_oldest_change_kept() {
+    let feed = ChangeFeed::new();
+    let epoch = epoch(&feed);
+    for id in 2..=(RECENT as i32 + 5) {
+        feed.publish(change(id));
+    }
+    let current = RECENT + 5;
+
+    // the changes 2 to 5 are forgotten
+    let last = format!("{}-1", epoch);
+    let mut events = Box::pin(feed.subscribe(Some(&last)));
+    assert_eq!(
+        ids(&ready(&mut events)),
+        vec![format!("R{}-{}", epoch, current)]
+    );
+
+    // the client has every change up to the oldest one kept
+    let last = format!("{}-5", epoch);
+    let mut events = Box::pin(feed.subscribe(Some(&last)));
+    let events = ids(&ready(&mut events));
+    assert_eq!(events.len(), RECENT);
+    assert_eq!(events[0], format!("C{}-6", epoch));
+    assert_eq!(events[RECENT - 1], format!("C{}-{}", epoch, current));
+}
+
+#[test]
+fn resume_from_another_epoch() {
+    let feed = ChangeFeed::new();
+    let epoch = epoch(&feed);
+    let mut live = Box::pin(feed.subscribe(None));
+
+    feed.reset();
+    let reset = ids(&ready(&mut live));
+    assert_eq!(reset.len(), 1);
+    assert!(reset[0].starts_with('R'));
+    assert!(!reset[0].contains(&epoch));
+
+    let last = format!("{}-1", epoch);
+    let mut events = Box::pin(feed.subscribe(Some(&last)));
+    assert_eq!(ids(&ready(&mut events)), reset);
+
+    let mut events = Box::pin(feed.subscribe(Some("not an id")));
+    assert_eq!(ids(&ready(&mut events)), reset);
+}
+
+#[test]
+fn a_lagging_subscriber_is_reset() {
+    let feed = ChangeFeed::new();
+    let mut events = Box::pin(feed.subscribe(None));
+    for id in 1..=(RECENT as i32 + 1) {
+        feed.publish(change(id));
+    }
+    let events = ready(&mut events);
+    assert!(matches!(events[0], Event::Reset { .. }));
+}
@@ -0,0 +1,48 @@
+// src/tests/cli.rs
+
+use crate::cli::{parse_csv, read_persons, table};
+
+#[test]
+fn parses_quoted_fields() {
+    let records =
+        parse_csv("id,first_name,last_name\r\n1,\"Léon, Jr\",\"say \"\"hi\"\"\"\n2,a,\"b\nc\"")
+            .unwrap();
+    assert_eq!(
+        records,
+        vec![
+            vec!["id", "first_name", "last_name"],
+            vec!["1", "Léon, Jr", "say \"hi\""],
+            vec!["2", "a", "b\nc"],
+        ]
+    );
+    assert!(parse_csv("a,\"b\n").is_err());
+}
+
+#[test]
+fn reads_persons_by_column_name() {
+    let persons =
+        read_persons("last_name,id,first_name\nGENGOUX,7,Léon\n\nDENEUX,,Joseph\n").unwrap();
+    assert_eq!(persons.len(), 2);
+    assert_eq!(persons[0].first_name, "Léon");
+    assert_eq!(persons[1].last_name, "DENEUX");
+}
+
+#[test]
+fn refuses_invalid_persons() {
+    let err = read_persons("first_name,last_name\nLéon,GENGOUX\n ,DENEUX\n").unwrap_err();
+    assert_eq!(err.to_string(), "line 3 : first_name must not be empty");
+    assert!(read_persons("first_name\nLéon\n").is_err());
+    assert!(read_persons("").is_err());
+}
+
+#[test]
+fn aligns_table_columns_by_characters() {
+    let rows = vec![
+        vec!["1".to_string(), "Léon".to_string()],
+        vec!["12".to_string(), "Jo".to_string()],
+    ];
+    assert_eq!(
+        table(&["id", "first_name"], &rows),
+        "id  first_name\n--  ----------\n1   Léon\n12  Jo\n"
+    );
+}
@@ -0,0 +1,43 @@
+// src/tests/cors.rs
+
+use crate::filters::is_same_origin;
+
+#[test]
+fn same_origin_by_host() {
+    assert!(is_same_origin(
+        "http://localhost:8085",
+        None,
+        None,
+        Some("localhost:8085")
+    ));
+    assert!(!is_same_origin(
+        "http://evil.example",
+        None,
+        None,
+        Some("localhost:8085")
+    ));
+    assert!(!is_same_origin("http://localhost:8085", None, None, None));
+}
+
+#[test]
+fn same_origin_behind_a_proxy() {
+    let forwarded = Some("persons.example, proxy.internal");
+    assert!(is_same_origin(
+        "https://persons.example",
+        None,
+        forwarded,
+        Some("backend:8085")
+    ));
+    assert!(!is_same_origin(
+        "https://backend:8085",
+        None,
+        forwarded,
+        Some("backend:8085")
+    ));
+    assert!(is_same_origin(
+        "https://persons.example",
+        Some("https://persons.example/"),
+        None,
+        Some("backend:8085")
+    ));
+}
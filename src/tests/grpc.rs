@@ -0,0 +1,119 @@
+// src/tests/grpc.rs
+
+use futures::StreamExt;
+use tonic::{Code, Request};
+
+use super::harness::TestApp;
+use crate::db;
+use crate::grpc::proto::person_service_server::PersonService;
+use crate::grpc::proto::{
+    CreateRequest, DeleteRequest, GetRequest, ListRequest, PersonInput, UpdateRequest,
+};
+use crate::grpc::PersonsService;
+use crate::rate_limit::{KeyStrategy, Quota, RateLimitConfig, RateLimiter};
+
+fn input(first_name: &str, last_name: &str) -> Option<PersonInput> {
+    Some(PersonInput {
+        first_name: first_name.to_string(),
+        last_name: last_name.to_string(),
+    })
+}
+
+#[tokio::test]
+#[ignore]
+async fn grpc_crud() {
+    let app = TestApp::new().await;
+    let service = PersonsService::new(app.pool.clone());
+
+    let created = service
+        .create(Request::new(CreateRequest {
+            person: input("Léon", "GRPC"),
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(created.first_name, "Léon");
+    assert!(created.updated_at > 0);
+
+    let found = service
+        .get(Request::new(GetRequest { id: created.id }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(found.last_name, "GRPC");
+
+    let updated = service
+        .update(Request::new(UpdateRequest {
+            id: created.id,
+            person: input("Léon", "G."),
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(updated.last_name, "G.");
+
+    // the fixtures are in the uncommitted test transaction, the service does not see them
+    let listed: Vec<_> = service
+        .list(Request::new(ListRequest {}))
+        .await
+        .unwrap()
+        .into_inner()
+        .collect()
+        .await;
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].as_ref().unwrap().id, created.id);
+
+    let refused = service
+        .create(Request::new(CreateRequest {
+            person: input(" ", "X"),
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(refused.code(), Code::InvalidArgument);
+
+    service
+        .delete(Request::new(DeleteRequest { id: created.id }))
+        .await
+        .unwrap();
+    let missing = service
+        .get(Request::new(GetRequest { id: created.id }))
+        .await
+        .unwrap_err();
+    assert_eq!(missing.code(), Code::NotFound);
+    let missing = service
+        .delete(Request::new(DeleteRequest { id: created.id }))
+        .await
+        .unwrap_err();
+    assert_eq!(missing.code(), Code::NotFound);
+    app.teardown().await;
+}
+
+#[tokio::test]
+async fn calls_over_the_quota_are_refused() {
+    // the pool opens no connection, the refused inputs never reach it
+    let pool = db::create_pg_pool("postgres://localhost/grpc")
+        .await
+        .unwrap();
+    let limiter = RateLimiter::new(RateLimitConfig {
+        key: KeyStrategy::Ip,
+        reads: Quota {
+            per_minute: 0,
+            burst: 0,
+        },
+        writes: Quota {
+            per_minute: 1,
+            burst: 1,
+        },
+    });
+    let service = PersonsService::new(pool).with_limiter(limiter);
+    let create = || {
+        Request::new(CreateRequest {
+            person: input(" ", "X"),
+        })
+    };
+
+    let refused = service.create(create()).await.unwrap_err();
+    assert_eq!(refused.code(), Code::InvalidArgument);
+    let limited = service.create(create()).await.unwrap_err();
+    assert_eq!(limited.code(), Code::ResourceExhausted);
+}
@@ -0,0 +1,264 @@
+// src/tests/harness.rs
+
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use sqlx::{Connect, Executor, PgConnection, PgPool};
+use tokio::sync::Mutex;
+use warp::filters::BoxedFilter;
+use warp::reply::Response;
+use warp::{Filter, Reply};
+
+use crate::changes::ChangeFeed;
+use crate::config::Config;
+use crate::db::{self, PgTx};
+use crate::filters;
+use crate::migrations;
+use crate::models::Person;
+use crate::repository::Repository;
+use crate::resource::{Duplicate, Resource, DUPLICATE_THRESHOLD};
+use crate::seed;
+
+///
+/// Replaces the database name of a postgres url
+///
+fn with_database(url: &str, name: &str) -> String {
+    let (base, query) = match url.find('?') {
+        Some(q) => (&url[..q], &url[q..]),
+        None => (url, ""),
+    };
+    let scheme_end = base.find("://").map_or(0, |i| i + 3);
+    let base = match base[scheme_end..].find('/') {
+        Some(slash) => &base[..scheme_end + slash],
+        None => base,
+    };
+    format!("{}/{}{}", base, name, query)
+}
+
+///
+/// A database created for one test, dropped with it
+///
+struct TestDatabase {
+    admin_url: String,
+    name: String,
+}
+
+impl TestDatabase {
+    async fn create(admin_url: &str) -> anyhow::Result<TestDatabase> {
+        let name = format!("test_{}", uuid::Uuid::new_v4().to_simple());
+        let mut conn = PgConnection::connect(admin_url).await?;
+        conn.execute(format!("CREATE DATABASE {};", name).as_str())
+            .await?;
+        Ok(TestDatabase {
+            admin_url: admin_url.to_string(),
+            name,
+        })
+    }
+
+    fn url(&self) -> String {
+        with_database(&self.admin_url, &self.name)
+    }
+
+    async fn drop_database(admin_url: &str, name: &str) -> anyhow::Result<()> {
+        let mut conn = PgConnection::connect(admin_url).await?;
+        sqlx::query(
+            "SELECT pg_terminate_backend(pid) FROM pg_stat_activity
+                WHERE datname = $1 AND pid <> pg_backend_pid();",
+        )
+        .bind(name)
+        .execute(&mut conn)
+        .await?;
+        conn.execute(format!("DROP DATABASE IF EXISTS {};", name).as_str())
+            .await?;
+        Ok(())
+    }
+}
+
+impl Drop for TestDatabase {
+    // runs even when the test panics, on its own runtime
+    // since the one of the test may be going away
+    fn drop(&mut self) {
+        let admin_url = self.admin_url.clone();
+        let name = self.name.clone();
+        let res = std::thread::spawn(move || {
+            let mut rt = tokio::runtime::Runtime::new()?;
+            rt.block_on(TestDatabase::drop_database(&admin_url, &name))
+        })
+        .join();
+        if let Ok(Err(err)) = res {
+            eprintln!("could not drop the test database {} : {}", self.name, err);
+        }
+    }
+}
+
+///
+/// A repository running every query in the transaction of the test
+/// nothing is ever committed, `rollback` undoes the whole test
+///
+pub struct TxRepository<T> {
+    tx: Arc<Mutex<Option<PgTx>>>,
+    resource: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for TxRepository<T> {
+    fn clone(&self) -> Self {
+        TxRepository {
+            tx: self.tx.clone(),
+            resource: PhantomData,
+        }
+    }
+}
+
+impl<T> TxRepository<T> {
+    async fn begin(pool: &PgPool) -> anyhow::Result<TxRepository<T>> {
+        Ok(TxRepository {
+            tx: Arc::new(Mutex::new(Some(db::begin(pool).await?))),
+            resource: PhantomData,
+        })
+    }
+
+    async fn rollback(&self) -> anyhow::Result<()> {
+        if let Some(tx) = self.tx.lock().await.take() {
+            tx.rollback().await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<T: Resource> Repository<T> for TxRepository<T> {
+    async fn list(&self) -> anyhow::Result<Vec<T>> {
+        let mut tx = self.tx.lock().await;
+        db::list_in::<T>(tx.as_mut().unwrap()).await
+    }
+
+    async fn find(&self, id: i32) -> anyhow::Result<T> {
+        let mut tx = self.tx.lock().await;
+        db::find_in::<T>(id, tx.as_mut().unwrap()).await
+    }
+
+    async fn add(&self, item: T::Insertable) -> anyhow::Result<T> {
+        let mut tx = self.tx.lock().await;
+        db::add_in::<T>(tx.as_mut().unwrap(), item).await
+    }
+
+    async fn update(&self, id: i32, item: T::Insertable) -> anyhow::Result<T> {
+        let mut tx = self.tx.lock().await;
+        db::update_in::<T>(id, item, tx.as_mut().unwrap()).await
+    }
+
+    async fn delete(&self, id: i32) -> anyhow::Result<i32> {
+        let mut tx = self.tx.lock().await;
+        db::delete_in::<T>(id, tx.as_mut().unwrap()).await
+    }
+
+    async fn similar(&self, item: &T::Insertable) -> anyhow::Result<Vec<(T, f32)>> {
+        let mut tx = self.tx.lock().await;
+        db::similar_in::<T>(item, DUPLICATE_THRESHOLD, tx.as_mut().unwrap()).await
+    }
+
+    async fn duplicates(&self, threshold: f32) -> anyhow::Result<Vec<Duplicate<T>>> {
+        let mut tx = self.tx.lock().await;
+        db::duplicates_in::<T>(threshold, tx.as_mut().unwrap()).await
+    }
+
+    async fn merge(&self, keep: i32, remove: i32) -> anyhow::Result<T> {
+        let mut tx = self.tx.lock().await;
+        db::merge_in::<T>(keep, remove, tx.as_mut().unwrap()).await
+    }
+
+    async fn merged_into(&self, id: i32) -> anyhow::Result<Option<i32>> {
+        let mut tx = self.tx.lock().await;
+        db::merged_into_in::<T>(id, tx.as_mut().unwrap()).await
+    }
+}
+
+///
+/// The whole API on its own database, migrated and seeded with the fixtures
+///
+pub struct TestApp {
+    pub api: BoxedFilter<(Response,)>,
+    pub repo: TxRepository<Person>,
+    /// the fixtures as inserted, ids included
+    pub persons: Vec<Person>,
+    /// outside of the test transaction, its changes are committed
+    pub pool: PgPool,
+    _database: TestDatabase,
+}
+
+impl TestApp {
+    ///
+    /// Builds the app with the test configuration
+    ///
+    /// # Panics
+    /// when `TEST_DATABASE_URL` is not set, a test needing the database
+    /// is `#[ignore]`d and only runs with `cargo test -- --ignored`
+    ///
+    pub async fn new() -> TestApp {
+        TestApp::with_config(|_| {}).await
+    }
+
+    ///
+    /// Builds the app after letting the test adjust the configuration
+    ///
+    pub async fn with_config(adjust: impl FnOnce(&mut Config)) -> TestApp {
+        let admin_url = std::env::var("TEST_DATABASE_URL")
+            .expect("TEST_DATABASE_URL is not set, the database tests cannot run");
+
+        let database = TestDatabase::create(&admin_url)
+            .await
+            .expect("could not create the test database");
+        let pool = db::create_pg_pool(&database.url()).await.unwrap();
+        migrations::run(&pool).await.unwrap();
+
+        let repo = TxRepository::begin(&pool).await.unwrap();
+        let mut persons = Vec::new();
+        for person in seed::fixtures("basic").unwrap().persons() {
+            persons.push(repo.add(person).await.unwrap());
+        }
+
+        let mut config = test_config();
+        adjust(&mut config);
+        let api = filters::api(pool.clone(), repo.clone(), ChangeFeed::new(), &config)
+            .map(Reply::into_response)
+            .boxed();
+
+        TestApp {
+            api,
+            repo,
+            persons,
+            pool,
+            _database: database,
+        }
+    }
+
+    ///
+    /// Rolls the test back and closes the pool,
+    /// the database is dropped with the app
+    ///
+    pub async fn teardown(self) {
+        self.repo.rollback().await.unwrap();
+        self.pool.close().await;
+    }
+}
+
+///
+/// The configuration of the tests : no rate limit, no cross origin
+///
+fn test_config() -> Config {
+    let mut config = Config::from_env();
+    config.rate_limit.reads.per_minute = 0;
+    config.rate_limit.writes.per_minute = 0;
+    config.cors.allowed_origins = Vec::new();
+    config
+}
+
+///
+/// Encodes a person as the add and modify forms do
+///
+pub fn form(first_name: &str, last_name: &str) -> String {
+    format!("first_name={}&last_name={}", first_name, last_name)
+}
+
+pub const FORM_CONTENT_TYPE: &str = "application/x-www-form-urlencoded";
@@ -0,0 +1,121 @@
+// src/tests/jobs.rs
+
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::JobConfig;
+use crate::jobs::worker::{run_within, settle, Status};
+use crate::jobs::JobFuture;
+use crate::retry::backoff;
+
+fn config() -> JobConfig {
+    JobConfig {
+        concurrency: 1,
+        poll_interval: Duration::from_millis(10),
+        timeout: Duration::from_millis(50),
+        base_delay: Duration::from_secs(10),
+        max_delay: Duration::from_secs(3600),
+        export_dir: "exports".into(),
+    }
+}
+
+fn job<F>(run: F) -> JobFuture
+where
+    F: Future<Output = anyhow::Result<()>> + Send + 'static,
+{
+    Box::pin(run)
+}
+
+#[test]
+fn backoff_doubles_up_to_the_max() {
+    let base = Duration::from_secs(10);
+    let max = Duration::from_secs(3600);
+    let delays: Vec<u64> = (0..=10)
+        .map(|attempts| backoff(base, max, attempts).as_secs())
+        .collect();
+    assert_eq!(
+        delays,
+        vec![10, 10, 20, 40, 80, 160, 320, 640, 1280, 2560, 3600]
+    );
+    // no overflow however many attempts
+    assert_eq!(backoff(base, max, 1000), max);
+    assert_eq!(backoff(base, max, i32::MAX), max);
+}
+
+#[test]
+fn a_failed_job_is_retried_then_dead() {
+    let config = config();
+    let failed = || Err("failed".to_string());
+
+    let (status, error, delay) = settle(failed(), 1, 3, true, &config);
+    assert_eq!(status, Status::Queued);
+    assert_eq!(error.as_deref(), Some("failed"));
+    assert_eq!(delay, Duration::from_secs(10));
+
+    let (status, _, delay) = settle(failed(), 2, 3, true, &config);
+    assert_eq!(status, Status::Queued);
+    assert_eq!(delay, Duration::from_secs(20));
+
+    let (status, error, delay) = settle(failed(), 3, 3, true, &config);
+    assert_eq!(status, Status::Dead);
+    assert_eq!(error.as_deref(), Some("failed"));
+    assert_eq!(delay, Duration::from_secs(0));
+}
+
+#[test]
+fn a_job_of_an_unknown_kind_is_dead() {
+    let (status, _, _) = settle(Err("unknown".to_string()), 1, 5, false, &config());
+    assert_eq!(status, Status::Dead);
+}
+
+#[test]
+fn a_done_job_is_done() {
+    let (status, error, _) = settle(Ok(()), 5, 5, true, &config());
+    assert_eq!(status, Status::Done);
+    assert_eq!(error, None);
+}
+
+#[tokio::test]
+async fn jobs_are_run_within_the_timeout() {
+    assert_eq!(
+        run_within(job(async { Ok(()) }), Duration::from_secs(1)).await,
+        Ok(())
+    );
+    assert_eq!(
+        run_within(
+            job(async { Err(anyhow::anyhow!("no such person")) }),
+            Duration::from_secs(1)
+        )
+        .await,
+        Err("no such person".to_string())
+    );
+
+    let panicked = run_within(
+        job(async { panic!("the job panicked") }),
+        Duration::from_secs(1),
+    )
+    .await;
+    assert!(panicked.unwrap_err().starts_with("panicked"));
+}
+
+#[tokio::test]
+async fn a_job_over_its_time_is_aborted() {
+    let finished = Arc::new(AtomicBool::new(false));
+    let job = {
+        let finished = finished.clone();
+        job(async move {
+            tokio::time::delay_for(Duration::from_millis(200)).await;
+            finished.store(true, Ordering::SeqCst);
+            Ok(())
+        })
+    };
+
+    let outcome = run_within(job, Duration::from_millis(20)).await;
+    assert!(outcome.unwrap_err().starts_with("aborted"));
+
+    // the job does not go on in the background
+    tokio::time::delay_for(Duration::from_millis(400)).await;
+    assert!(!finished.load(Ordering::SeqCst));
+}
@@ -0,0 +1,22 @@
+// src/tests/mod.rs
+
+//! Integration tests of the routes against a real Postgres.
+//! They need `TEST_DATABASE_URL`, the url of a database whose user may
+//! create databases; they are `#[ignore]`d, `cargo test -- --ignored` runs them
+//! and fails when the url is not set.
+
+mod app;
+mod assets;
+mod cache;
+mod changes;
+mod cli;
+mod cors;
+mod grpc;
+mod harness;
+mod jobs;
+mod rate_limit;
+mod resource;
+mod routes;
+mod seed;
+mod server;
+mod webhooks;
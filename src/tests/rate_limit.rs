@@ -0,0 +1,91 @@
+// src/tests/rate_limit.rs
+
+use std::net::IpAddr;
+use std::time::Duration;
+
+use crate::rate_limit::{key_of, Access, KeyStrategy, Quota, RateLimitConfig, RateLimiter};
+
+fn limiter(reads: Quota, writes: Quota) -> RateLimiter {
+    RateLimiter::new(RateLimitConfig {
+        key: KeyStrategy::Ip,
+        reads,
+        writes,
+    })
+}
+
+#[test]
+fn burst_then_rejected() {
+    let limiter = limiter(
+        Quota {
+            per_minute: 60,
+            burst: 2,
+        },
+        Quota {
+            per_minute: 0,
+            burst: 0,
+        },
+    );
+    assert!(limiter.check(Access::Read, "ip:1".to_string()).is_ok());
+    assert!(limiter.check(Access::Read, "ip:1".to_string()).is_ok());
+    assert!(limiter.check(Access::Read, "ip:1".to_string()).is_err());
+    // another client, and the unlimited writes, have their own buckets
+    assert!(limiter.check(Access::Read, "ip:2".to_string()).is_ok());
+    for _ in 0..100 {
+        assert!(limiter.check(Access::Write, "ip:1".to_string()).is_ok());
+    }
+}
+
+#[test]
+fn sweep_keeps_buckets_refilling_under_their_own_quota() {
+    // the reads refill within milliseconds, the writes within a minute
+    let limiter = limiter(
+        Quota {
+            per_minute: 6000,
+            burst: 1,
+        },
+        Quota {
+            per_minute: 1,
+            burst: 1,
+        },
+    );
+    assert!(limiter.check(Access::Write, "ip:w".to_string()).is_ok());
+    assert!(limiter.check(Access::Write, "ip:w".to_string()).is_err());
+    std::thread::sleep(Duration::from_millis(50));
+
+    // enough new clients to sweep the full buckets
+    for client in 0..11_000 {
+        let _ = limiter.check(Access::Read, format!("ip:{}", client));
+    }
+    assert!(limiter.check(Access::Write, "ip:w".to_string()).is_err());
+}
+
+#[test]
+fn client_keys() {
+    let ip: Option<IpAddr> = Some([10, 0, 0, 1].into());
+    assert_eq!(
+        key_of(KeyStrategy::Ip, Some("Bearer abc"), None, None, ip),
+        "ip:10.0.0.1"
+    );
+    assert_eq!(
+        key_of(
+            KeyStrategy::Token,
+            Some("Bearer abc"),
+            Some("def"),
+            None,
+            ip
+        ),
+        "token:abc"
+    );
+    assert_eq!(
+        key_of(KeyStrategy::Token, Some("Basic xyz"), Some("def"), None, ip),
+        "token:def"
+    );
+    assert_eq!(
+        key_of(KeyStrategy::User, None, None, Some("léon"), ip),
+        "user:léon"
+    );
+    assert_eq!(
+        key_of(KeyStrategy::User, None, None, None, None),
+        "ip:unknown"
+    );
+}
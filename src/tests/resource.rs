@@ -0,0 +1,214 @@
+// src/tests/resource.rs
+
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgRow;
+
+use crate::models::{InsertablePerson, Person, MAX_NAME_LENGTH};
+use crate::resource::{
+    contains_pattern, ListVersion, Resource, Statements, Value, DUPLICATES_PER_ROW,
+};
+
+///
+/// A resource with neither update time nor duplicates, only for its SQL
+///
+#[derive(Serialize, Clone)]
+struct Company {
+    id: i32,
+    name: String,
+    city: String,
+}
+
+#[derive(Deserialize, Clone)]
+struct InsertableCompany {
+    name: String,
+    city: String,
+}
+
+impl Resource for Company {
+    type Insertable = InsertableCompany;
+
+    const TABLE: &'static str = "companies";
+    const COLUMNS: &'static [&'static str] = &["name", "city"];
+
+    const NAME: &'static str = "company";
+    const PLURAL: &'static str = "companies";
+
+    const LIST_ROUTE: &'static str = "/companies";
+    const ITEM_ROUTE: &'static str = "/companies/{id}";
+    const ADD_ROUTE: &'static str = "/companies/add";
+
+    const LIST_TEMPLATE: &'static str = "companies.html";
+    const EDIT_TEMPLATE: &'static str = "modify_company.html";
+    const ADD_TEMPLATE: &'static str = "add_company.html";
+
+    fn id(&self) -> i32 {
+        self.id
+    }
+
+    fn from_row(_row: &PgRow) -> Company {
+        unreachable!("the companies are never read from a database here")
+    }
+
+    fn values(company: &InsertableCompany) -> Vec<Value> {
+        vec![
+            Value::Text(company.name.clone()),
+            Value::Text(company.city.clone()),
+        ]
+    }
+
+    fn with_id(id: i32, company: InsertableCompany) -> Company {
+        Company {
+            id,
+            name: company.name,
+            city: company.city,
+        }
+    }
+}
+
+#[test]
+fn statements_of_a_plain_resource() {
+    let statements = Statements::of::<Company>();
+    assert_eq!(
+        statements.list,
+        "SELECT id, name, city FROM companies ORDER BY id;"
+    );
+    assert_eq!(
+        statements.find,
+        "SELECT id, name, city FROM companies WHERE id = $1;"
+    );
+    assert_eq!(
+        statements.insert,
+        "INSERT INTO companies (name, city) VALUES ($1, $2) RETURNING id, name, city;"
+    );
+    assert_eq!(
+        statements.update,
+        "UPDATE companies SET name = $1, city = $2 WHERE id = $3 RETURNING id, name, city;"
+    );
+    assert_eq!(
+        statements.delete,
+        "DELETE FROM companies WHERE id = $1 RETURNING id, name, city;"
+    );
+    assert!(statements.version.is_none());
+    assert!(statements.similar.is_none());
+    assert!(statements.duplicates.is_none());
+}
+
+#[test]
+fn statements_with_update_time_and_duplicates() {
+    let statements = Statements::of::<Person>();
+    let updated_at = "(extract(epoch from updated_at) * 1000)::bigint";
+    assert_eq!(
+        statements.find,
+        format!(
+            "SELECT id, first_name, last_name, {} FROM persons WHERE id = $1;",
+            updated_at
+        )
+    );
+    assert!(statements.insert.ends_with(&format!(
+        "RETURNING id, first_name, last_name, {};",
+        updated_at
+    )));
+    assert_eq!(
+        statements.version.unwrap(),
+        "SELECT count(*), (extract(epoch from max(updated_at)) * 1000)::bigint FROM persons;"
+    );
+    assert!(statements
+        .similar
+        .unwrap()
+        .contains("similarity(lower(first_name || ' ' || last_name), lower($1))"));
+    let duplicates = statements.duplicates.unwrap();
+    assert!(duplicates.contains("FROM persons"));
+    // a lookup in the trigram index for each row, not a join of every pair
+    assert!(duplicates.contains("CROSS JOIN LATERAL"));
+    assert!(duplicates.contains("WHERE lower(first_name || ' ' || last_name) % a.text"));
+    assert!(duplicates.contains(&format!("LIMIT {}", DUPLICATES_PER_ROW)));
+}
+
+#[test]
+fn search_statements() {
+    let (count, page) = Statements::search::<Company>(&[]);
+    assert_eq!(count, "SELECT count(*) FROM companies;");
+    assert_eq!(
+        page,
+        "SELECT id, name, city FROM companies ORDER BY id LIMIT $1 OFFSET $2;"
+    );
+
+    let (count, page) = Statements::search::<Company>(&["name", "city"]);
+    let filter = "WHERE name ILIKE $1 ESCAPE '\\' AND city ILIKE $2 ESCAPE '\\'";
+    assert_eq!(count, format!("SELECT count(*) FROM companies {};", filter));
+    assert_eq!(
+        page,
+        format!(
+            "SELECT id, name, city FROM companies {} ORDER BY id LIMIT $3 OFFSET $4;",
+            filter
+        )
+    );
+}
+
+#[test]
+fn contains_patterns_take_wildcards_literally() {
+    assert_eq!(contains_pattern("gen"), "%gen%");
+    assert_eq!(contains_pattern(""), "%%");
+    assert_eq!(contains_pattern("50%_off\\"), "%50\\%\\_off\\\\%");
+}
+
+#[test]
+fn the_version_needs_every_update_time() {
+    let company = Company {
+        id: 1,
+        name: "ACME".to_string(),
+        city: "Lyon".to_string(),
+    };
+    assert_eq!(ListVersion::of(&[company]), None);
+
+    let person = |id, updated_at| Person {
+        id,
+        first_name: "Léon".to_string(),
+        last_name: "GENGOUX".to_string(),
+        updated_at,
+    };
+    assert_eq!(
+        ListVersion::of(&[person(1, Some(10)), person(2, Some(30))]),
+        Some(ListVersion {
+            count: 2,
+            updated_at: Some(30),
+        })
+    );
+    assert_eq!(
+        ListVersion::of(&[person(1, Some(10)), person(2, None)]),
+        None
+    );
+    assert_eq!(
+        ListVersion::of::<Person>(&[]),
+        Some(ListVersion {
+            count: 0,
+            updated_at: None,
+        })
+    );
+}
+
+#[test]
+fn persons_need_both_names() {
+    let person = |first_name: &str, last_name: &str| InsertablePerson {
+        first_name: first_name.to_string(),
+        last_name: last_name.to_string(),
+    };
+    assert!(Person::validate(&person("Léon", "GENGOUX")).is_ok());
+    assert_eq!(
+        Person::validate(&person(" ", "GENGOUX")).unwrap_err().field,
+        "first_name"
+    );
+    assert_eq!(
+        Person::validate(&person("Léon", "")).unwrap_err().field,
+        "last_name"
+    );
+    // the length is counted in characters, not in bytes
+    let longest = "é".repeat(MAX_NAME_LENGTH);
+    assert!(Person::validate(&person(&longest, "GENGOUX")).is_ok());
+    assert_eq!(
+        Person::validate(&person("Léon", &format!("{}x", longest)))
+            .unwrap_err()
+            .field,
+        "last_name"
+    );
+}
@@ -0,0 +1,670 @@
+// src/tests/routes.rs
+
+use warp::http::StatusCode;
+use warp::test::request;
+
+use super::harness::{form, TestApp, FORM_CONTENT_TYPE};
+use crate::changes::ChangeFeed;
+use crate::config::Config;
+use crate::models::InsertablePerson;
+use crate::repository::{MemoryPersonRepository, Repository};
+use crate::{db, filters, openapi};
+
+macro_rules! app {
+    () => {
+        app!(|_| {})
+    };
+    ($adjust:expr) => {
+        TestApp::with_config($adjust).await
+    };
+}
+
+fn body(res: &warp::http::Response<hyper::body::Bytes>) -> String {
+    String::from_utf8_lossy(res.body()).into_owned()
+}
+
+#[tokio::test]
+async fn every_route_is_documented() {
+    // the pool opens no connection before the first query,
+    // so the filters can be built without a database
+    let pool = db::create_pg_pool("postgres://localhost/openapi")
+        .await
+        .unwrap();
+    // every optional route is built
+    let mut config = Config::from_env();
+    config.graphiql = true;
+    let _api = filters::api(
+        pool,
+        MemoryPersonRepository::new(),
+        ChangeFeed::new(),
+        &config,
+    );
+
+    let undocumented = openapi::undocumented_routes();
+    assert!(
+        undocumented.is_empty(),
+        "routes missing from the OpenAPI document : {:?}",
+        undocumented
+    );
+    let unrouted = openapi::unrouted_operations();
+    assert!(
+        unrouted.is_empty(),
+        "operations of the OpenAPI document without a route : {:?}",
+        unrouted
+    );
+}
+
+#[tokio::test]
+#[ignore]
+async fn home_page() {
+    let app = app!();
+    let res = request().method("GET").path("/").reply(&app.api).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(body(&res).contains("<html"));
+    app.teardown().await;
+}
+
+#[tokio::test]
+#[ignore]
+async fn list_persons_page() {
+    let app = app!();
+    let res = request()
+        .method("GET")
+        .path("/persons")
+        .reply(&app.api)
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let page = body(&res);
+    for person in &app.persons {
+        assert!(page.contains(&person.last_name));
+    }
+    app.teardown().await;
+}
+
+#[tokio::test]
+#[ignore]
+async fn modify_person_page() {
+    let app = app!();
+    let leon = &app.persons[1];
+    let res = request()
+        .method("GET")
+        .path(&format!("/persons/{}", leon.id))
+        .reply(&app.api)
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(body(&res).contains("Léon"));
+
+    let res = request()
+        .method("GET")
+        .path("/persons/0")
+        .reply(&app.api)
+        .await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    let res = request()
+        .method("GET")
+        .path("/persons/leon")
+        .reply(&app.api)
+        .await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    app.teardown().await;
+}
+
+#[tokio::test]
+#[ignore]
+async fn add_person_page() {
+    let app = app!();
+    let res = request().method("GET").path("/add").reply(&app.api).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(body(&res).contains("<form"));
+    app.teardown().await;
+}
+
+#[tokio::test]
+#[ignore]
+async fn add_person() {
+    let app = app!();
+    let res = request()
+        .method("POST")
+        .path("/add")
+        .header("content-type", FORM_CONTENT_TYPE)
+        .body(form("Ada", "LOVELACE"))
+        .reply(&app.api)
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(body(&res).contains("LOVELACE"));
+
+    let persons = app.repo.list().await.unwrap();
+    assert_eq!(persons.len(), app.persons.len() + 1);
+    app.teardown().await;
+}
+
+#[tokio::test]
+#[ignore]
+async fn add_person_with_a_bad_form() {
+    let app = app!();
+    let res = request()
+        .method("POST")
+        .path("/add")
+        .header("content-type", FORM_CONTENT_TYPE)
+        .body("first_name=Ada")
+        .reply(&app.api)
+        .await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(app.repo.list().await.unwrap().len(), app.persons.len());
+    app.teardown().await;
+}
+
+#[tokio::test]
+#[ignore]
+async fn update_person() {
+    let app = app!();
+    let james = &app.persons[0];
+    let res = request()
+        .method("PUT")
+        .path(&format!("/persons/{}", james.id))
+        .header("content-type", FORM_CONTENT_TYPE)
+        .body(form("Jim", "ANDERSON"))
+        .reply(&app.api)
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(app.repo.find(james.id).await.unwrap().first_name, "Jim");
+
+    let res = request()
+        .method("PUT")
+        .path("/persons/0")
+        .header("content-type", FORM_CONTENT_TYPE)
+        .body(form("Jim", "ANDERSON"))
+        .reply(&app.api)
+        .await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    app.teardown().await;
+}
+
+#[tokio::test]
+#[ignore]
+async fn delete_person() {
+    let app = app!();
+    let joseph = &app.persons[2];
+    let res = request()
+        .method("DELETE")
+        .path(&format!("/persons/{}", joseph.id))
+        .reply(&app.api)
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(!body(&res).contains("DENEUX"));
+    assert!(app.repo.find(joseph.id).await.is_err());
+    app.teardown().await;
+}
+
+#[tokio::test]
+#[ignore]
+async fn unknown_route() {
+    let app = app!();
+    let res = request()
+        .method("GET")
+        .path("/nowhere")
+        .reply(&app.api)
+        .await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    app.teardown().await;
+}
+
+#[tokio::test]
+#[ignore]
+async fn health_checks() {
+    let app = app!();
+    let res = request()
+        .method("GET")
+        .path("/healthz")
+        .reply(&app.api)
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = request()
+        .method("GET")
+        .path("/readyz")
+        .reply(&app.api)
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(body(&res).contains("\"up\""));
+    app.teardown().await;
+}
+
+#[tokio::test]
+#[ignore]
+async fn metrics() {
+    let app = app!();
+    request()
+        .method("GET")
+        .path("/persons")
+        .reply(&app.api)
+        .await;
+    let res = request()
+        .method("GET")
+        .path("/metrics")
+        .reply(&app.api)
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(body(&res).contains("http_requests_total"));
+    app.teardown().await;
+}
+
+#[tokio::test]
+#[ignore]
+async fn static_assets() {
+    let app = app!();
+    let res = request()
+        .method("GET")
+        .path("/static/style.css")
+        .reply(&app.api)
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let etag = res.headers()["etag"].to_str().unwrap().to_string();
+
+    let res = request()
+        .method("GET")
+        .path("/static/style.css")
+        .header("if-none-match", etag)
+        .reply(&app.api)
+        .await;
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+
+    let res = request()
+        .method("GET")
+        .path("/static/missing.css")
+        .reply(&app.api)
+        .await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    app.teardown().await;
+}
+
+#[tokio::test]
+#[ignore]
+async fn api_documentation() {
+    let app = app!();
+    let res = request()
+        .method("GET")
+        .path("/openapi.json")
+        .reply(&app.api)
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(body(&res).contains("\"openapi\""));
+
+    let res = request().method("GET").path("/docs").reply(&app.api).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(
+        !body(&res).contains("https://"),
+        "the docs page loads a remote file"
+    );
+    app.teardown().await;
+}
+
+#[tokio::test]
+#[ignore]
+async fn writes_are_rate_limited() {
+    let app = app!(|config| {
+        config.rate_limit.writes.per_minute = 1;
+        config.rate_limit.writes.burst = 1;
+    });
+    let add = || {
+        request()
+            .method("POST")
+            .path("/add")
+            .header("content-type", FORM_CONTENT_TYPE)
+            .body(form("Ada", "LOVELACE"))
+    };
+    assert_eq!(add().reply(&app.api).await.status(), StatusCode::OK);
+
+    let res = add().reply(&app.api).await;
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert!(res.headers().contains_key("retry-after"));
+    app.teardown().await;
+}
+
+#[tokio::test]
+#[ignore]
+async fn cross_origin_requests() {
+    let app = app!(|config| {
+        config.cors.allowed_origins = vec!["https://spa.example".to_string()];
+    });
+    let res = request()
+        .method("OPTIONS")
+        .path("/persons")
+        .header("origin", "https://spa.example")
+        .header("access-control-request-method", "GET")
+        .reply(&app.api)
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(
+        res.headers()["access-control-allow-origin"],
+        "https://spa.example"
+    );
+
+    let res = request()
+        .method("GET")
+        .path("/persons")
+        .header("origin", "https://evil.example")
+        .reply(&app.api)
+        .await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    // a form posted by our own page behind a proxy rewriting `Host`
+    let res = request()
+        .method("GET")
+        .path("/persons")
+        .header("origin", "https://persons.example")
+        .header("host", "backend:8085")
+        .header("x-forwarded-host", "persons.example")
+        .reply(&app.api)
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    app.teardown().await;
+}
+
+#[tokio::test]
+#[ignore]
+async fn no_cors_policy_without_allowed_origins() {
+    let app = app!();
+    let res = request()
+        .method("GET")
+        .path("/persons")
+        .header("origin", "https://other.example")
+        .reply(&app.api)
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(!res.headers().contains_key("access-control-allow-origin"));
+    app.teardown().await;
+}
+
+#[tokio::test]
+#[ignore]
+async fn compressed_replies() {
+    let app = app!(|config| {
+        config.compression.enabled = true;
+        config.compression.min_size = 0;
+    });
+    let res = request()
+        .method("GET")
+        .path("/persons")
+        .header("accept-encoding", "gzip")
+        .reply(&app.api)
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-encoding"], "gzip");
+    app.teardown().await;
+}
+
+#[tokio::test]
+#[ignore]
+async fn webhook_subscriptions() {
+    let app = app!();
+    let res = request()
+        .method("POST")
+        .path("/webhooks")
+        .json(&serde_json::json!({
+            "url": "https://hooks.example/persons",
+            "events": ["person.created", "person.deleted"],
+        }))
+        .reply(&app.api)
+        .await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let created: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+    assert!(created["secret"].as_str().map_or(false, |s| !s.is_empty()));
+    let id = created["id"].as_i64().unwrap();
+
+    let res = request()
+        .method("GET")
+        .path("/webhooks")
+        .reply(&app.api)
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(body(&res).contains("https://hooks.example/persons"));
+    assert!(!body(&res).contains("secret"));
+
+    let res = request()
+        .method("POST")
+        .path("/webhooks")
+        .json(&serde_json::json!({ "url": "ftp://hooks.example", "events": ["*"] }))
+        .reply(&app.api)
+        .await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let res = request()
+        .method("DELETE")
+        .path(&format!("/webhooks/{}", id))
+        .reply(&app.api)
+        .await;
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+    let res = request()
+        .method("DELETE")
+        .path(&format!("/webhooks/{}", id))
+        .reply(&app.api)
+        .await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    app.teardown().await;
+}
+
+#[tokio::test]
+#[ignore]
+async fn admin_jobs() {
+    let app = app!();
+    let res = request()
+        .method("POST")
+        .path("/admin/jobs/export")
+        .reply(&app.api)
+        .await;
+    assert_eq!(res.status(), StatusCode::SEE_OTHER);
+    assert_eq!(res.headers()["location"], "/admin/jobs");
+
+    let res = request()
+        .method("GET")
+        .path("/admin/jobs")
+        .reply(&app.api)
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(body(&res).contains("export_persons"));
+    assert!(body(&res).contains("queued"));
+    app.teardown().await;
+}
+
+#[tokio::test]
+#[ignore]
+async fn conditional_get() {
+    let app = app!();
+    let res = request()
+        .method("GET")
+        .path("/persons")
+        .reply(&app.api)
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let list_etag = res.headers()["etag"].to_str().unwrap().to_string();
+    assert!(list_etag.starts_with("W/"));
+    assert!(res.headers().contains_key("last-modified"));
+
+    let res = request()
+        .method("GET")
+        .path("/persons")
+        .header("if-none-match", &list_etag)
+        .reply(&app.api)
+        .await;
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    assert!(res.body().is_empty());
+
+    let person = &app.persons[0];
+    let path = format!("/persons/{}", person.id);
+    let res = request().method("GET").path(&path).reply(&app.api).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let etag = res.headers()["etag"].to_str().unwrap().to_string();
+    let last_modified = res.headers()["last-modified"].to_str().unwrap().to_string();
+    assert!(!etag.starts_with("W/"));
+
+    let res = request()
+        .method("GET")
+        .path(&path)
+        .header("if-none-match", &etag)
+        .reply(&app.api)
+        .await;
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    let res = request()
+        .method("GET")
+        .path(&path)
+        .header("if-modified-since", &last_modified)
+        .reply(&app.api)
+        .await;
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+
+    app.repo
+        .update(
+            person.id,
+            InsertablePerson {
+                first_name: "Jimmy".to_string(),
+                last_name: person.last_name.clone(),
+            },
+        )
+        .await
+        .unwrap();
+    let res = request()
+        .method("GET")
+        .path(&path)
+        .header("if-none-match", &etag)
+        .reply(&app.api)
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let res = request()
+        .method("GET")
+        .path("/persons")
+        .header("if-none-match", &list_etag)
+        .reply(&app.api)
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    app.teardown().await;
+}
+
+#[tokio::test]
+#[ignore]
+async fn duplicates_and_merge() {
+    let app = app!();
+    // a variant of "James ANDERSON" from the fixtures
+    let res = request()
+        .method("POST")
+        .path("/add")
+        .header("content-type", FORM_CONTENT_TYPE)
+        .body(form("James", "Anderson"))
+        .reply(&app.api)
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(res.headers().contains_key("warning"));
+    assert!(body(&res).contains("looks like a duplicate"));
+
+    let res = request()
+        .method("GET")
+        .path("/persons/duplicates")
+        .reply(&app.api)
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let duplicates: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+    let pair = &duplicates[0];
+    let keep = pair["first"]["id"].as_i64().unwrap();
+    let remove = pair["second"]["id"].as_i64().unwrap();
+    assert_eq!(keep, i64::from(app.persons[0].id));
+
+    let res = request()
+        .method("POST")
+        .path(&format!("/persons/{}/merge", keep))
+        .json(&serde_json::json!({ "from": keep }))
+        .reply(&app.api)
+        .await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let res = request()
+        .method("POST")
+        .path(&format!("/persons/{}/merge", keep))
+        .json(&serde_json::json!({ "from": remove }))
+        .reply(&app.api)
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(app.repo.list().await.unwrap().len(), app.persons.len());
+
+    let res = request()
+        .method("GET")
+        .path(&format!("/persons/{}", remove))
+        .reply(&app.api)
+        .await;
+    assert_eq!(res.status(), StatusCode::MOVED_PERMANENTLY);
+    assert_eq!(
+        res.headers()["location"],
+        format!("/persons/{}", keep).as_str()
+    );
+    app.teardown().await;
+}
+
+#[tokio::test]
+#[ignore]
+async fn graphql_queries_and_mutations() {
+    let app = app!();
+    let graphql = |query: &str| {
+        request()
+            .method("POST")
+            .path("/graphql")
+            .json(&serde_json::json!({ "query": query }))
+    };
+
+    let res = graphql(
+        r#"mutation { createPerson(input: {firstName: "Léon", lastName: "GRAPHQL"}) { id lastName } }"#,
+    )
+    .reply(&app.api)
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let created: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(created["data"]["createPerson"]["lastName"], "GRAPHQL");
+    let id = created["data"]["createPerson"]["id"].as_i64().unwrap();
+
+    let res = graphql(
+        r#"{ persons(filter: {lastName: "graph"}, first: 10) { totalCount items { firstName } } }"#,
+    )
+    .reply(&app.api)
+    .await;
+    let page: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(page["data"]["persons"]["totalCount"], 1);
+    assert_eq!(page["data"]["persons"]["items"][0]["firstName"], "Léon");
+
+    // the filter is a text, not a pattern
+    let res = graphql(r#"{ persons(filter: {lastName: "GRAPH%"}) { totalCount } }"#)
+        .reply(&app.api)
+        .await;
+    let page: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(page["data"]["persons"]["totalCount"], 0);
+
+    let res = request()
+        .method("GET")
+        .path(&format!(
+            "/graphql?query=mutation%20%7B%20deletePerson(id%3A%20{})%20%7D",
+            id
+        ))
+        .reply(&app.api)
+        .await;
+    let refused: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+    assert!(refused["data"].is_null());
+
+    let res =
+        graphql(r#"mutation { createPerson(input: {firstName: " ", lastName: "X"}) { id } }"#)
+            .reply(&app.api)
+            .await;
+    let refused: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(refused["errors"][0]["extensions"]["code"], "INVALID");
+
+    let res = graphql(&format!("mutation {{ deletePerson(id: {}) }}", id))
+        .reply(&app.api)
+        .await;
+    let deleted: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(deleted["data"]["deletePerson"], true);
+
+    let res = graphql(&format!("{{ person(id: {}) {{ id }} }}", id))
+        .reply(&app.api)
+        .await;
+    let missing: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+    assert!(missing["data"]["person"].is_null());
+    app.teardown().await;
+}
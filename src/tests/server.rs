@@ -0,0 +1,42 @@
+// src/tests/server.rs
+
+use hyper::{Body, Request, StatusCode};
+
+use crate::server::{redirect_to_https, strip_port};
+
+fn get(host: Option<&str>, uri: &str) -> Request<Body> {
+    let mut req = Request::get(uri);
+    if let Some(host) = host {
+        req = req.header("host", host);
+    }
+    req.body(Body::empty()).unwrap()
+}
+
+#[test]
+fn strips_ports() {
+    assert_eq!(strip_port("example.com:8080"), "example.com");
+    assert_eq!(strip_port("example.com"), "example.com");
+    assert_eq!(strip_port("[::1]:8080"), "[::1]");
+    assert_eq!(strip_port("[::1]"), "[::1]");
+}
+
+#[test]
+fn redirects_to_the_same_path_over_https() {
+    let res = redirect_to_https(&get(Some("example.com:8080"), "/persons?page=2"), 443);
+    assert_eq!(res.status(), StatusCode::MOVED_PERMANENTLY);
+    assert_eq!(
+        res.headers()["location"],
+        "https://example.com/persons?page=2"
+    );
+
+    let res = redirect_to_https(&get(Some("[::1]:8080"), "/"), 8443);
+    assert_eq!(res.headers()["location"], "https://[::1]:8443/");
+}
+
+#[test]
+fn refuses_to_redirect_without_host() {
+    let res = redirect_to_https(&get(None, "/persons"), 443);
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    let res = redirect_to_https(&get(Some(":8080"), "/persons"), 443);
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+}
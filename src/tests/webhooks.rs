@@ -0,0 +1,83 @@
+// src/tests/webhooks.rs
+
+use std::net::IpAddr;
+
+use crate::webhooks::{is_public, NewWebhook};
+
+fn webhook(url: &str) -> NewWebhook {
+    NewWebhook {
+        url: url.to_string(),
+        events: vec!["person.created".to_string()],
+        secret: None,
+    }
+}
+
+fn ip(ip: &str) -> IpAddr {
+    ip.parse().unwrap()
+}
+
+#[test]
+fn public_addresses() {
+    for public in &[
+        "93.184.216.34",
+        "8.8.8.8",
+        "2606:2800:220:1::248",
+        "::ffff:8.8.8.8",
+    ] {
+        assert!(is_public(ip(public)), "{} is public", public);
+    }
+    for private in &[
+        "127.0.0.1",
+        "10.1.2.3",
+        "172.16.0.1",
+        "192.168.1.1",
+        "169.254.169.254",
+        "100.64.0.1",
+        "0.0.0.0",
+        "255.255.255.255",
+        "224.0.0.1",
+        "::1",
+        "::",
+        "fd00::1",
+        "fe80::1",
+        "::ffff:127.0.0.1",
+        "::ffff:169.254.169.254",
+    ] {
+        assert!(!is_public(ip(private)), "{} is not public", private);
+    }
+}
+
+#[test]
+fn webhook_targets() {
+    assert!(webhook("https://hooks.example/persons")
+        .validate(false)
+        .is_ok());
+    assert!(webhook("http://93.184.216.34:8080/")
+        .validate(false)
+        .is_ok());
+
+    for url in &[
+        "http://localhost:8080/hook",
+        "http://LOCALHOST/hook",
+        "http://api.localhost/hook",
+        "http://127.0.0.1/hook",
+        "http://169.254.169.254/latest/meta-data",
+        "http://10.0.0.5/hook",
+        "http://[::1]:8080/hook",
+        "http://[fe80::1]/hook",
+    ] {
+        let invalid = webhook(url).validate(false).unwrap_err();
+        assert_eq!(invalid.field, "url", "{} is refused", url);
+    }
+    assert!(webhook("http://localhost:8080/hook").validate(true).is_ok());
+}
+
+#[test]
+fn webhook_urls_and_events() {
+    assert!(webhook("ftp://hooks.example").validate(false).is_err());
+    assert!(webhook("/hook").validate(false).is_err());
+
+    let mut unknown = webhook("https://hooks.example");
+    unknown.events = vec!["person.renamed".to_string()];
+    assert_eq!(unknown.validate(false).unwrap_err().field, "events");
+}
@@ -0,0 +1,108 @@
+// src/tls.rs
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, bail};
+use tokio_rustls::rustls::internal::pemfile;
+use tokio_rustls::rustls::sign::{self, CertifiedKey};
+use tokio_rustls::rustls::{ClientHello, NoClientAuth, ResolvesServerCert, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+
+use crate::config::TlsConfig;
+
+///
+/// Serves the certificate currently loaded
+/// the watcher swaps it when the files change, the handshakes in progress
+/// keep the one they started with
+///
+struct CertResolver {
+    current: RwLock<CertifiedKey>,
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<CertifiedKey> {
+        Some(self.current.read().unwrap().clone())
+    }
+}
+
+///
+/// Reads the certificate chain and the private key (PKCS8 or RSA) from PEM files
+///
+fn load_certified_key(cert_path: &Path, key_path: &Path) -> anyhow::Result<CertifiedKey> {
+    let certs = pemfile::certs(&mut BufReader::new(File::open(cert_path)?))
+        .map_err(|_| anyhow!("invalid certificate file {}", cert_path.display()))?;
+    if certs.is_empty() {
+        bail!("no certificate in {}", cert_path.display());
+    }
+
+    let mut keys = pemfile::pkcs8_private_keys(&mut BufReader::new(File::open(key_path)?))
+        .map_err(|_| anyhow!("invalid key file {}", key_path.display()))?;
+    if keys.is_empty() {
+        keys = pemfile::rsa_private_keys(&mut BufReader::new(File::open(key_path)?))
+            .map_err(|_| anyhow!("invalid key file {}", key_path.display()))?;
+    }
+    let key = keys
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("no private key in {}", key_path.display()))?;
+    let signing_key =
+        sign::any_supported_type(&key).map_err(|_| anyhow!("unsupported private key type"))?;
+
+    Ok(CertifiedKey::new(certs, Arc::new(signing_key)))
+}
+
+fn last_modified(paths: &[&PathBuf]) -> Option<SystemTime> {
+    paths
+        .iter()
+        .filter_map(|path| std::fs::metadata(path).ok()?.modified().ok())
+        .max()
+}
+
+///
+/// Builds the TLS acceptor and starts the task reloading the certificate
+/// when the certificate or key file changes
+///
+pub fn acceptor(config: &TlsConfig) -> anyhow::Result<TlsAcceptor> {
+    let resolver = Arc::new(CertResolver {
+        current: RwLock::new(load_certified_key(&config.cert_path, &config.key_path)?),
+    });
+
+    let mut server_config = ServerConfig::new(NoClientAuth::new());
+    server_config.cert_resolver = resolver.clone();
+    server_config.set_protocols(&[b"h2".to_vec(), b"http/1.1".to_vec()]);
+
+    tokio::spawn(watch(config.clone(), resolver));
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
+
+///
+/// Polls the modification time of the files
+/// a certificate failing to load is logged and the previous one kept
+///
+async fn watch(config: TlsConfig, resolver: Arc<CertResolver>) {
+    let files = [&config.cert_path, &config.key_path];
+    let mut loaded = last_modified(&files);
+    let mut interval = tokio::time::interval(config.reload_interval);
+
+    loop {
+        interval.tick().await;
+        let modified = last_modified(&files);
+        if modified == loaded {
+            continue;
+        }
+        // wait for both files to be written before reading them
+        tokio::time::delay_for(Duration::from_secs(1)).await;
+        match load_certified_key(&config.cert_path, &config.key_path) {
+            Ok(key) => {
+                *resolver.current.write().unwrap() = key;
+                tracing::info!("TLS : certificate reloaded");
+            }
+            Err(err) => tracing::error!("TLS : could not reload the certificate : {}", err),
+        }
+        loaded = modified;
+    }
+}
@@ -0,0 +1,280 @@
+// src/webhooks/mod.rs
+
+use std::net::IpAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::postgres::PgRow;
+use sqlx::{PgPool, Row};
+use warp::http::Uri;
+
+use crate::db::{self, PgTx};
+use crate::resource::Invalid;
+
+pub mod worker;
+
+/// The operations reported for every resource, as `<name>.<operation>`
+const OPERATIONS: &[&str] = &["created", "updated", "deleted"];
+
+///
+/// A webhook subscription, its secret is never shown again after the creation
+///
+#[derive(Serialize, Debug, Clone, JsonSchema)]
+pub struct Webhook {
+    pub id: i32,
+    pub url: String,
+    pub events: Vec<String>,
+}
+
+///
+/// The body creating a subscription
+/// `events` are `person.created`, `person.updated`, `person.deleted` or `*`;
+/// a secret is generated when none is given
+///
+#[derive(Deserialize, Debug, Clone, JsonSchema)]
+pub struct NewWebhook {
+    pub url: String,
+    pub events: Vec<String>,
+    pub secret: Option<String>,
+}
+
+///
+/// A created subscription, with the secret signing its deliveries
+///
+#[derive(Serialize, Debug, Clone, JsonSchema)]
+pub struct CreatedWebhook {
+    #[serde(flatten)]
+    pub webhook: Webhook,
+    pub secret: String,
+}
+
+///
+/// One event to deliver to one webhook, and the attempts made so far
+///
+#[derive(Serialize, Debug, Clone, JsonSchema)]
+pub struct Delivery {
+    pub id: i64,
+    pub event: String,
+    /// pending, delivered or failed
+    pub status: String,
+    pub next_attempt_at: String,
+    pub attempts: Vec<Attempt>,
+}
+
+#[derive(Serialize, Debug, Clone, JsonSchema)]
+pub struct Attempt {
+    pub attempted_at: String,
+    pub status_code: Option<i32>,
+    pub error: Option<String>,
+    pub duration_ms: i32,
+}
+
+impl NewWebhook {
+    ///
+    /// Checks the url and the event types
+    /// unless `allow_private`, the url may not name a loopback, private or link-local host;
+    /// the addresses a name resolves to are checked at every delivery
+    ///
+    pub fn validate(&self, allow_private: bool) -> Result<(), Invalid> {
+        let uri: Uri = self
+            .url
+            .parse()
+            .map_err(|_| Invalid::new("url", "is not a valid url"))?;
+        let host = match (uri.scheme_str(), uri.host()) {
+            (Some("http"), Some(host)) | (Some("https"), Some(host)) => host,
+            _ => return Err(Invalid::new("url", "must be an absolute http or https url")),
+        };
+        if !allow_private && !is_public_host(host) {
+            return Err(Invalid::new(
+                "url",
+                "must not target a local or private address",
+            ));
+        }
+
+        if self.events.is_empty() {
+            return Err(Invalid::new("events", "must not be empty"));
+        }
+        for event in &self.events {
+            let known = event == "*"
+                || match event.find('.') {
+                    Some(dot) => dot > 0 && OPERATIONS.contains(&&event[dot + 1..]),
+                    None => false,
+                };
+            if !known {
+                return Err(Invalid::new("events", "has an unknown event type"));
+            }
+        }
+        Ok(())
+    }
+}
+
+///
+/// False for `localhost` and the addresses that are not public, true for the other names
+///
+fn is_public_host(host: &str) -> bool {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if host.eq_ignore_ascii_case("localhost") || host.to_ascii_lowercase().ends_with(".localhost") {
+        return false;
+    }
+    host.parse::<IpAddr>().map_or(true, is_public)
+}
+
+///
+/// True for the addresses a webhook may be delivered to:
+/// not loopback, private, shared, link-local, unspecified, broadcast, documentation or multicast
+///
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(a == 0
+                || ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                // 100.64.0.0/10, shared by the carriers' NATs
+                || (a == 100 && b & 0xc0 == 64))
+        }
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            // ::ffff:a.b.c.d
+            if segments[..5].iter().all(|&s| s == 0) && segments[5] == 0xffff {
+                let [a, b] = segments[6].to_be_bytes();
+                let [c, d] = segments[7].to_be_bytes();
+                return is_public(IpAddr::from([a, b, c, d]));
+            }
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // fc00::/7, unique local
+                || segments[0] & 0xfe00 == 0xfc00
+                // fe80::/10, link-local
+                || segments[0] & 0xffc0 == 0xfe80)
+        }
+    }
+}
+
+fn row_to_webhook(row: &PgRow) -> Webhook {
+    let events: String = row.get(2);
+    Webhook {
+        id: row.get(0),
+        url: row.get(1),
+        events: events.split(',').map(str::to_string).collect(),
+    }
+}
+
+pub async fn list(pool: &PgPool) -> anyhow::Result<Vec<Webhook>> {
+    let mut tx = db::begin(pool).await?;
+    let webhooks = sqlx::query("SELECT id, url, events FROM webhooks ORDER BY id;")
+        .map(|row: PgRow| row_to_webhook(&row))
+        .fetch_all(&mut tx)
+        .await?;
+    tx.commit().await?;
+    Ok(webhooks)
+}
+
+pub async fn create(pool: &PgPool, new: NewWebhook) -> anyhow::Result<CreatedWebhook> {
+    let secret = new
+        .secret
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_simple().to_string());
+    let mut tx = db::begin(pool).await?;
+    let webhook = sqlx::query(
+        "INSERT INTO webhooks (url, events, secret)
+                VALUES ( $1, $2, $3 )
+                RETURNING id, url, events;",
+    )
+    .bind(&new.url)
+    .bind(new.events.join(","))
+    .bind(&secret)
+    .map(|row: PgRow| row_to_webhook(&row))
+    .fetch_one(&mut tx)
+    .await?;
+    tx.commit().await?;
+    Ok(CreatedWebhook { webhook, secret })
+}
+
+/// Returns the number of subscriptions deleted
+pub async fn delete(id: i32, pool: &PgPool) -> anyhow::Result<u64> {
+    let mut tx = db::begin(pool).await?;
+    let deleted = sqlx::query("DELETE FROM webhooks WHERE id = $1;")
+        .bind(id)
+        .execute(&mut tx)
+        .await?;
+    tx.commit().await?;
+    Ok(deleted)
+}
+
+///
+/// The latest deliveries of a webhook with their attempts, most recent first
+///
+pub async fn deliveries(webhook_id: i32, pool: &PgPool) -> anyhow::Result<Vec<Delivery>> {
+    let mut tx = db::begin(pool).await?;
+    let mut deliveries: Vec<Delivery> = sqlx::query(
+        "SELECT id, event, status, next_attempt_at::text
+                FROM webhook_outbox
+                WHERE webhook_id = $1
+                ORDER BY id DESC
+                LIMIT 50;",
+    )
+    .bind(webhook_id)
+    .map(|row: PgRow| Delivery {
+        id: row.get(0),
+        event: row.get(1),
+        status: row.get(2),
+        next_attempt_at: row.get(3),
+        attempts: Vec::new(),
+    })
+    .fetch_all(&mut tx)
+    .await?;
+
+    for delivery in deliveries.iter_mut() {
+        delivery.attempts = sqlx::query(
+            "SELECT attempted_at::text, status_code, error, duration_ms
+                    FROM webhook_attempts
+                    WHERE outbox_id = $1
+                    ORDER BY id;",
+        )
+        .bind(delivery.id)
+        .map(|row: PgRow| Attempt {
+            attempted_at: row.get(0),
+            status_code: row.get(1),
+            error: row.get(2),
+            duration_ms: row.get(3),
+        })
+        .fetch_all(&mut tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(deliveries)
+}
+
+///
+/// Writes `event` in the outbox of every webhook subscribed to it
+/// called by the `db` mutations inside their transaction,
+/// so an event is recorded if and only if its change is committed
+///
+pub async fn enqueue_in<T: Serialize>(tx: &mut PgTx, event: &str, data: &T) -> anyhow::Result<u64> {
+    let occurred_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let payload = json!({
+        "event": event,
+        "occurred_at": occurred_at,
+        "data": data,
+    });
+    let queued = sqlx::query(
+        "INSERT INTO webhook_outbox (webhook_id, event, payload)
+                SELECT id, $1, $2 FROM webhooks
+                WHERE $1 = ANY(string_to_array(events, ','))
+                   OR '*' = ANY(string_to_array(events, ','));",
+    )
+    .bind(event)
+    .bind(payload.to_string())
+    .execute(tx)
+    .await?;
+    Ok(queued)
+}
@@ -0,0 +1,261 @@
+// src/webhooks/worker.rs
+
+use std::net::ToSocketAddrs;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use futures::future;
+use hmac::{Hmac, Mac, NewMac};
+use hyper::client::HttpConnector;
+use hyper::{Body, Client, Method, Request, Uri};
+use hyper_rustls::HttpsConnector;
+use sha2::Sha256;
+use sqlx::postgres::PgRow;
+use sqlx::{PgPool, Row};
+use tokio::task::JoinHandle;
+
+use crate::config::WebhookConfig;
+use crate::db;
+use crate::retry;
+use crate::webhooks::is_public;
+
+type HttpsClient = Client<HttpsConnector<HttpConnector>>;
+
+///
+/// A delivery claimed by this worker
+///
+struct Due {
+    id: i64,
+    event: String,
+    payload: String,
+    attempts: i32,
+    url: String,
+    secret: String,
+}
+
+///
+/// Starts the delivery worker
+/// several instances can run it, a delivery is only claimed by one of them
+///
+pub fn spawn(pool: PgPool, config: WebhookConfig) -> JoinHandle<()> {
+    tokio::spawn(run(pool, config))
+}
+
+async fn run(pool: PgPool, config: WebhookConfig) {
+    let client: HttpsClient = Client::builder().build(HttpsConnector::new());
+    loop {
+        match deliver_due(&pool, &client, &config).await {
+            // a full batch, there may be more waiting
+            Ok(count) if count as i64 == config.batch_size => continue,
+            Ok(_) => {}
+            Err(err) => tracing::warn!("WEBHOOKS : could not deliver : {}", err),
+        }
+        tokio::time::delay_for(config.poll_interval).await;
+    }
+}
+
+///
+/// Claims the deliveries due and attempts them concurrently
+/// returns the number of deliveries attempted
+///
+async fn deliver_due(
+    pool: &PgPool,
+    client: &HttpsClient,
+    config: &WebhookConfig,
+) -> anyhow::Result<usize> {
+    let due = claim(pool, config).await?;
+    let count = due.len();
+    future::join_all(
+        due.into_iter()
+            .map(|due| attempt(pool, client, config, due)),
+    )
+    .await
+    .into_iter()
+    .collect::<anyhow::Result<Vec<()>>>()?;
+    Ok(count)
+}
+
+///
+/// Takes the pending deliveries due, skipping those another worker holds
+/// their next attempt is pushed past the timeout, so a worker dying
+/// in the middle of an attempt only delays it
+///
+async fn claim(pool: &PgPool, config: &WebhookConfig) -> anyhow::Result<Vec<Due>> {
+    let lease = (config.timeout + Duration::from_secs(30)).as_secs_f64();
+    let mut tx = db::begin(pool).await?;
+    let due = sqlx::query(
+        "UPDATE webhook_outbox o
+                SET next_attempt_at = now() + make_interval(secs => $2)
+                FROM webhooks w
+                WHERE o.webhook_id = w.id
+                  AND o.id IN (
+                    SELECT id FROM webhook_outbox
+                    WHERE status = 'pending' AND next_attempt_at <= now()
+                    ORDER BY next_attempt_at, id
+                    LIMIT $1
+                    FOR UPDATE SKIP LOCKED)
+                RETURNING o.id, o.event, o.payload, o.attempts, w.url, w.secret;",
+    )
+    .bind(config.batch_size)
+    .bind(lease)
+    .map(|row: PgRow| Due {
+        id: row.get(0),
+        event: row.get(1),
+        payload: row.get(2),
+        attempts: row.get(3),
+        url: row.get(4),
+        secret: row.get(5),
+    })
+    .fetch_all(&mut tx)
+    .await?;
+    tx.commit().await?;
+    Ok(due)
+}
+
+///
+/// Posts one delivery and records the attempt
+///
+async fn attempt(
+    pool: &PgPool,
+    client: &HttpsClient,
+    config: &WebhookConfig,
+    due: Due,
+) -> anyhow::Result<()> {
+    let start = Instant::now();
+    let sent = post(client, &due, config.allow_private_targets);
+    let outcome = match tokio::time::timeout(config.timeout, sent).await {
+        Ok(Ok(status)) if (200..300).contains(&status) => Ok(status),
+        Ok(Ok(status)) => Err((Some(status), format!("answered {}", status))),
+        Ok(Err(err)) => Err((None, err.to_string())),
+        Err(_) => Err((None, format!("no answer within {:?}", config.timeout))),
+    };
+    let duration_ms = start.elapsed().as_millis() as i32;
+
+    let attempts = due.attempts + 1;
+    let (status, status_code, error, delay) = match outcome {
+        Ok(code) => {
+            tracing::info!(
+                "WEBHOOKS : {} {} delivered to {}",
+                due.event,
+                due.id,
+                due.url
+            );
+            ("delivered", Some(code), None, Duration::from_secs(0))
+        }
+        Err((code, error)) if attempts >= config.max_attempts => {
+            tracing::warn!(
+                "WEBHOOKS : {} {} failed for good : {}",
+                due.event,
+                due.id,
+                error
+            );
+            ("failed", code, Some(error), Duration::from_secs(0))
+        }
+        Err((code, error)) => {
+            let delay = retry::backoff(config.base_delay, config.max_delay, attempts);
+            tracing::info!(
+                "WEBHOOKS : {} {} failed : {}, retrying in {:?}",
+                due.event,
+                due.id,
+                error,
+                delay
+            );
+            ("pending", code, Some(error), delay)
+        }
+    };
+
+    let mut tx = db::begin(pool).await?;
+    sqlx::query(
+        "INSERT INTO webhook_attempts (outbox_id, status_code, error, duration_ms)
+                VALUES ( $1, $2, $3, $4 );",
+    )
+    .bind(due.id)
+    .bind(status_code)
+    .bind(error)
+    .bind(duration_ms)
+    .execute(&mut tx)
+    .await?;
+    sqlx::query(
+        "UPDATE webhook_outbox
+                SET status = $2, attempts = $3, next_attempt_at = now() + make_interval(secs => $4)
+                WHERE id = $1;",
+    )
+    .bind(due.id)
+    .bind(status)
+    .bind(attempts)
+    .bind(delay.as_secs_f64())
+    .execute(&mut tx)
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+///
+/// Sends the payload, returns the status code of the answer
+///
+async fn post(client: &HttpsClient, due: &Due, allow_private: bool) -> anyhow::Result<i32> {
+    if !allow_private {
+        check_target(&due.url).await?;
+    }
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(&due.url)
+        .header("content-type", "application/json")
+        .header("x-webhook-id", due.id.to_string())
+        .header("x-webhook-event", due.event.as_str())
+        .header("x-webhook-timestamp", timestamp.to_string())
+        .header(
+            "x-webhook-signature",
+            format!("sha256={}", sign(&due.secret, timestamp, &due.payload)),
+        )
+        .body(Body::from(due.payload.clone()))?;
+    let response = client.request(request).await?;
+    Ok(i32::from(response.status().as_u16()))
+}
+
+///
+/// Refuses a url whose host resolves to an address that is not public
+/// checked before every attempt, since a name can point elsewhere after the creation;
+/// the connection resolves the name again, a DNS answer changing in between is not caught
+///
+async fn check_target(url: &str) -> anyhow::Result<()> {
+    let uri: Uri = url.parse()?;
+    let host = uri
+        .host()
+        .unwrap_or_default()
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_string();
+    let port = uri.port_u16().unwrap_or_else(|| match uri.scheme_str() {
+        Some("https") => 443,
+        _ => 80,
+    });
+    let addrs = tokio::task::spawn_blocking(move || {
+        (host.as_str(), port)
+            .to_socket_addrs()
+            .map(|addrs| addrs.collect::<Vec<_>>())
+    })
+    .await??;
+    if let Some(addr) = addrs.iter().find(|addr| !is_public(addr.ip())) {
+        anyhow::bail!(
+            "the target resolves to {}, which is not a public address",
+            addr.ip()
+        );
+    }
+    Ok(())
+}
+
+///
+/// HMAC-SHA256 of `<timestamp>.<payload>` with the secret of the webhook, in hex
+/// signing the timestamp lets the receiver refuse replayed deliveries
+///
+pub fn sign(secret: &str, timestamp: u64, payload: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_varkey(secret.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(payload.as_bytes());
+    format!("{:x}", mac.finalize().into_bytes())
+}